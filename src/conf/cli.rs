@@ -20,6 +20,8 @@ pub struct Cli {
     pub verbose: bool,
     #[arg(short = 'p', long = "partitions", value_delimiter = ',')]
     pub partitions: Vec<String>,
+    #[arg(long = "sysroot")]
+    pub sysroot: Option<PathBuf>,
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
@@ -45,14 +47,49 @@ pub enum Commands {
     },
     Storage,
     Modules,
-    Conflicts,
+    Module {
+        #[command(subcommand)]
+        action: ModuleAction,
+    },
+    Conflicts {
+        #[arg(long)]
+        duplicates: bool,
+    },
     Diagnostics,
-    #[command(name = "system-action")]
-    SystemAction {
+    Doctor {
+        #[arg(long)]
+        refresh: bool,
+    },
+    Plan,
+    Explain {
+        path: PathBuf,
+    },
+    Status {
+        #[arg(long)]
+        verify: bool,
+        #[arg(long, requires = "verify")]
+        pid: Option<i32>,
+    },
+    Relabel {
         #[arg(long)]
-        action: String,
+        module: Option<String>,
         #[arg(long)]
-        value: Option<String>,
+        dry_run: bool,
+    },
+    Teardown,
+    Reload {
+        #[arg(long)]
+        module: String,
+    },
+    Snapshot {
+        #[command(subcommand)]
+        action: SnapshotAction,
+    },
+    Serve {
+        #[arg(long, default_value = defs::CONTROL_SOCKET)]
+        socket: PathBuf,
+        #[arg(long, default_value_t = 30)]
+        drift_interval: u64,
     },
     Poaceae {
         #[arg(short, long, default_value = defs::POACEAE_MOUNT_POINT)]
@@ -63,6 +100,62 @@ pub enum Commands {
     },
 }
 
+impl Commands {
+    // Commands that never mount anything, and so may read the system tree from an extracted
+    // image instead of the live root.
+    pub fn uses_sysroot(&self) -> bool {
+        matches!(
+            self,
+            Self::Plan
+                | Self::Conflicts { .. }
+                | Self::Diagnostics
+                | Self::Explain { .. }
+                | Self::Relabel { .. }
+        )
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ModuleAction {
+    Enable {
+        id: String,
+        #[arg(long)]
+        snapshot: bool,
+    },
+    Disable {
+        id: String,
+        #[arg(long)]
+        snapshot: bool,
+    },
+    Remove {
+        id: String,
+        #[arg(long)]
+        snapshot: bool,
+    },
+    SkipMount {
+        id: String,
+        #[arg(long)]
+        clear: bool,
+        #[arg(long)]
+        snapshot: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SnapshotAction {
+    List,
+    Create {
+        #[arg(long, default_value = "Manual Backup")]
+        reason: String,
+    },
+    Delete {
+        id: String,
+    },
+    Restore {
+        id: String,
+    },
+}
+
 #[derive(Subcommand, Debug)]
 pub enum PoaceaeAction {
     Hide {
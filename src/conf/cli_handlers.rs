@@ -1,35 +1,80 @@
 // Copyright 2026 Hybrid Mount Developers
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::{fs::File, path::Path};
+use std::{
+    collections::HashMap,
+    fs::File,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use anyhow::{Context, Result, bail};
 use serde::Serialize;
 
 use crate::{
     conf::{
-        cli::{Cli, PoaceaeAction},
+        cli::{Cli, ModuleAction, PoaceaeAction, SnapshotAction},
         config::{self, Config},
+        rpc,
     },
     core::{
+        error::{ErrorKind, TypedError},
         inventory,
-        inventory::model as modules,
-        ops::{backup as granary, planner},
+        inventory::{
+            lifecycle::{self, LifecycleAction},
+            model as modules,
+        },
+        journal,
+        ops::{backup as granary, explain, planner, relabel, reload, verify},
+        state::RuntimeState,
         storage,
     },
     defs,
-    sys::poaceae,
+    sys::{poaceae, probe},
     utils,
 };
 
 #[derive(Serialize)]
-struct DiagnosticIssueJson {
+pub struct DiagnosticIssueJson {
     level: String,
     context: String,
     message: String,
 }
 
-fn load_config(cli: &Cli) -> Result<Config> {
+#[derive(Serialize)]
+struct PlanLayerJson {
+    module: String,
+    path: PathBuf,
+}
+
+#[derive(Serialize)]
+struct PlanOperationJson {
+    partition: String,
+    target: String,
+    layers: Vec<PlanLayerJson>,
+}
+
+#[derive(Serialize)]
+struct PlanJson {
+    overlay_ops: Vec<PlanOperationJson>,
+    overlay_modules: Vec<String>,
+    magic_modules: Vec<String>,
+    magic_paths: HashMap<String, Vec<PathBuf>>,
+    ignored: Vec<planner::IgnoredPath>,
+    decisions: Vec<planner::PlanDecision>,
+}
+
+// Commands that never mount take --sysroot over the configured one, so they can run against
+// extracted images.
+fn load_sysroot_config(cli: &Cli) -> Result<Config> {
+    let mut config = load_config(cli)?;
+    if let Some(sysroot) = &cli.sysroot {
+        config.sysroot = sysroot.clone();
+    }
+    Ok(config)
+}
+
+pub fn load_config(cli: &Cli) -> Result<Config> {
     if let Some(config_path) = &cli.config {
         return Config::from_file(config_path).with_context(|| {
             format!(
@@ -76,48 +121,83 @@ pub fn handle_show_config(cli: &Cli) -> Result<()> {
     Ok(())
 }
 
-pub fn handle_save_config(cli: &Cli, payload: &str) -> Result<()> {
-    if let Ok(old_config) = load_config(cli)
-        && let Err(e) = granary::create_snapshot(&old_config, "Auto-Backup", "Pre-WebUI Save")
-    {
-        log::warn!("Failed to create Backup: {}", e);
+fn decode_payload(payload: &str) -> Result<Vec<u8>> {
+    // Slicing below assumes whole ASCII digit pairs.
+    if !payload.len().is_multiple_of(2) || !payload.is_ascii() {
+        bail!(TypedError::new(
+            ErrorKind::ConfigInvalid,
+            "Failed to decode hex payload: expected an even number of hex digits",
+        ));
     }
 
-    let json_bytes = (0..payload.len())
+    let bytes = (0..payload.len())
         .step_by(2)
         .map(|i| u8::from_str_radix(&payload[i..i + 2], 16))
         .collect::<Result<Vec<u8>, _>>()
-        .context("Failed to decode hex payload")?;
+        .map_err(|e| {
+            TypedError::new(
+                ErrorKind::ConfigInvalid,
+                format!("Failed to decode hex payload: {}", e),
+            )
+        })?;
 
-    let config: Config =
-        serde_json::from_slice(&json_bytes).context("Failed to parse config JSON payload")?;
+    Ok(bytes)
+}
+
+// Snapshots the config being replaced before writing the new one.
+pub fn save_config(cli: &Cli, config: &Config) -> Result<()> {
+    if let Ok(old_config) = load_config(cli)
+        && let Err(e) = granary::create_snapshot(&old_config, "Auto-Backup", "Pre-WebUI Save")
+    {
+        log::warn!("Failed to create Backup: {}", e);
+    }
 
     config
         .save_to_file(defs::CONFIG_FILE)
-        .context("Failed to save config file")?;
+        .context("Failed to save config file")
+}
+
+pub fn handle_save_config(cli: &Cli, payload: &str) -> Result<()> {
+    let json_bytes = decode_payload(payload)?;
+
+    let config: Config = serde_json::from_slice(&json_bytes).map_err(|e| {
+        TypedError::new(
+            ErrorKind::ConfigInvalid,
+            format!("Failed to parse config JSON payload: {}", e),
+        )
+    })?;
+
+    save_config(cli, &config)?;
 
     println!("Configuration saved successfully.");
 
     Ok(())
 }
 
-pub fn handle_save_module_rules(module_id: &str, payload: &str) -> Result<()> {
+pub fn save_module_rules(module_id: &str, rules: config::ModuleRules) -> Result<()> {
     utils::validate_module_id(module_id)?;
-    let json_bytes = (0..payload.len())
-        .step_by(2)
-        .map(|i| u8::from_str_radix(&payload[i..i + 2], 16))
-        .collect::<Result<Vec<u8>, _>>()
-        .context("Failed to decode hex payload")?;
 
-    let new_rules: config::ModuleRules =
-        serde_json::from_slice(&json_bytes).context("Failed to parse module rules JSON")?;
     let mut config = Config::load_default().unwrap_or_default();
 
-    config.rules.insert(module_id.to_string(), new_rules);
+    config.rules.insert(module_id.to_string(), rules);
 
     config
         .save_to_file(defs::CONFIG_FILE)
-        .context("Failed to update config file with new rules")?;
+        .context("Failed to update config file with new rules")
+}
+
+pub fn handle_save_module_rules(module_id: &str, payload: &str) -> Result<()> {
+    utils::validate_module_id(module_id)?;
+    let json_bytes = decode_payload(payload)?;
+
+    let new_rules: config::ModuleRules = serde_json::from_slice(&json_bytes).map_err(|e| {
+        TypedError::new(
+            ErrorKind::ConfigInvalid,
+            format!("Failed to parse module rules JSON: {}", e),
+        )
+    })?;
+
+    save_module_rules(module_id, new_rules)?;
 
     println!("Module rules saved for {} into config.toml", module_id);
 
@@ -131,40 +211,82 @@ pub fn handle_storage() -> Result<()> {
 pub fn handle_modules(cli: &Cli) -> Result<()> {
     let config = load_config(cli)?;
 
-    modules::print_list(&config).context("Failed to list modules")
+    let infos = modules::list(&config).context("Failed to list modules")?;
+
+    let json = serde_json::to_string(&infos).context("Failed to serialize module list")?;
+
+    println!("{}", json);
+
+    Ok(())
 }
 
-pub fn handle_conflicts(cli: &Cli) -> Result<()> {
+pub fn handle_module(cli: &Cli, action: &ModuleAction) -> Result<()> {
     let config = load_config(cli)?;
 
-    let module_list = inventory::scan(&config.moduledir, &config)
+    let (id, lifecycle_action, snapshot) = match action {
+        ModuleAction::Enable { id, snapshot } => (id, LifecycleAction::Enable, *snapshot),
+        ModuleAction::Disable { id, snapshot } => (id, LifecycleAction::Disable, *snapshot),
+        ModuleAction::Remove { id, snapshot } => (id, LifecycleAction::Remove, *snapshot),
+        ModuleAction::SkipMount {
+            id,
+            clear: false,
+            snapshot,
+        } => (id, LifecycleAction::SkipMount, *snapshot),
+        ModuleAction::SkipMount {
+            id,
+            clear: true,
+            snapshot,
+        } => (id, LifecycleAction::UnskipMount, *snapshot),
+    };
+
+    let report = lifecycle::apply(&config, id, lifecycle_action, snapshot)
+        .with_context(|| format!("Failed to update module {}", id))?;
+
+    let json = serde_json::to_string(&report).context("Failed to serialize module report")?;
+
+    println!("{}", json);
+
+    Ok(())
+}
+
+pub fn conflicts(config: &Config, duplicates: bool) -> Result<Vec<planner::ConflictEntry>> {
+    let module_list = inventory::scan(&config.moduledir, config)
         .context("Failed to scan modules for conflict analysis")?;
 
-    let plan = planner::generate(&config, &module_list, &config.moduledir)
+    let plan = planner::generate(config, &module_list, &config.moduledir)
         .context("Failed to generate plan for conflict analysis")?;
 
-    let report = plan.analyze();
+    let report = plan.analyze(&config.moduledir);
 
-    let json =
-        serde_json::to_string(&report.conflicts).context("Failed to serialize conflict report")?;
+    Ok(if duplicates {
+        report.duplicates
+    } else {
+        report.conflicts
+    })
+}
+
+pub fn handle_conflicts(cli: &Cli, duplicates: bool) -> Result<()> {
+    let config = load_sysroot_config(cli)?;
+
+    let entries = conflicts(&config, duplicates)?;
+
+    let json = serde_json::to_string(&entries).context("Failed to serialize conflict report")?;
 
     println!("{}", json);
 
     Ok(())
 }
 
-pub fn handle_diagnostics(cli: &Cli) -> Result<()> {
-    let config = load_config(cli)?;
-
-    let module_list = inventory::scan(&config.moduledir, &config)
+pub fn diagnostics(config: &Config) -> Result<Vec<DiagnosticIssueJson>> {
+    let module_list = inventory::scan(&config.moduledir, config)
         .context("Failed to scan modules for diagnostics")?;
 
-    let plan = planner::generate(&config, &module_list, &config.moduledir)
+    let plan = planner::generate(config, &module_list, &config.moduledir)
         .context("Failed to generate plan for diagnostics")?;
 
-    let report = plan.analyze();
+    let report = plan.analyze(&config.moduledir);
 
-    let json_issues: Vec<DiagnosticIssueJson> = report
+    Ok(report
         .diagnostics
         .into_iter()
         .map(|i| DiagnosticIssueJson {
@@ -176,7 +298,13 @@ pub fn handle_diagnostics(cli: &Cli) -> Result<()> {
             context: i.context,
             message: i.message,
         })
-        .collect();
+        .collect())
+}
+
+pub fn handle_diagnostics(cli: &Cli) -> Result<()> {
+    let config = load_sysroot_config(cli)?;
+
+    let json_issues = diagnostics(&config)?;
 
     let json =
         serde_json::to_string(&json_issues).context("Failed to serialize diagnostics report")?;
@@ -186,52 +314,184 @@ pub fn handle_diagnostics(cli: &Cli) -> Result<()> {
     Ok(())
 }
 
-pub fn handle_system_action(cli: &Cli, action: &str, value: Option<&str>) -> Result<()> {
+pub fn handle_doctor(refresh: bool) -> Result<()> {
+    let caps = if refresh {
+        probe::refresh().context("Failed to probe kernel capabilities")?
+    } else {
+        probe::capabilities().clone()
+    };
+
+    let json = serde_json::to_string(&caps).context("Failed to serialize capability report")?;
+
+    println!("{}", json);
+
+    Ok(())
+}
+
+pub fn handle_plan(cli: &Cli) -> Result<()> {
+    let config = load_sysroot_config(cli)?;
+
+    let module_list =
+        inventory::scan(&config.moduledir, &config).context("Failed to scan modules for plan")?;
+
+    let plan = planner::generate(&config, &module_list, &config.moduledir)
+        .context("Failed to generate mount plan")?;
+
+    let overlay_ops = plan
+        .overlay_ops
+        .iter()
+        .map(|op| PlanOperationJson {
+            partition: op.partition_name.clone(),
+            target: op.target.clone(),
+            layers: op
+                .lowerdirs
+                .iter()
+                .map(|layer| PlanLayerJson {
+                    module: utils::extract_module_id(layer).unwrap_or_else(|| "UNKNOWN".into()),
+                    path: layer.clone(),
+                })
+                .collect(),
+        })
+        .collect();
+
+    let output = PlanJson {
+        overlay_ops,
+        overlay_modules: plan.overlay_module_ids,
+        magic_modules: plan.magic_module_ids,
+        magic_paths: plan.magic_paths,
+        ignored: plan.ignored,
+        decisions: plan.decisions,
+    };
+
+    let json = serde_json::to_string(&output).context("Failed to serialize mount plan")?;
+
+    println!("{}", json);
+
+    Ok(())
+}
+
+pub fn handle_explain(cli: &Cli, path: &Path) -> Result<()> {
+    let config = load_sysroot_config(cli)?;
+
+    let module_list = inventory::scan(&config.moduledir, &config)
+        .context("Failed to scan modules for path explanation")?;
+
+    let plan = planner::generate(&config, &module_list, &config.moduledir)
+        .context("Failed to generate plan for path explanation")?;
+
+    let report = explain::explain(&plan, &config, &config.moduledir, path)
+        .with_context(|| format!("Failed to explain {}", path.display()))?;
+
+    let json = serde_json::to_string(&report).context("Failed to serialize path explanation")?;
+
+    println!("{}", json);
+
+    Ok(())
+}
+
+pub fn handle_status(verify: bool, pid: Option<i32>) -> Result<()> {
+    let json = if verify {
+        let report = verify::verify(pid).context("Failed to verify mounts")?;
+        serde_json::to_string(&report).context("Failed to serialize drift report")?
+    } else {
+        let state = RuntimeState::load().context("Failed to load runtime state")?;
+        serde_json::to_string(&state).context("Failed to serialize runtime state")?
+    };
+
+    println!("{}", json);
+
+    Ok(())
+}
+
+pub fn handle_relabel(cli: &Cli, module: Option<&str>, dry_run: bool) -> Result<()> {
+    let config = load_sysroot_config(cli)?;
+
+    let report =
+        relabel::relabel(&config, module, dry_run).context("Failed to relabel module storage")?;
+
+    let json = serde_json::to_string(&report).context("Failed to serialize relabel report")?;
+
+    println!("{}", json);
+
+    Ok(())
+}
+
+pub fn handle_teardown() -> Result<()> {
+    let report = journal::teardown().context("Failed to tear down mounts")?;
+
+    if !report.unmounted.is_empty()
+        && let Ok(mut state) = RuntimeState::load()
+    {
+        state.overlay_modules.clear();
+        state.magic_modules.clear();
+        state.active_mounts.clear();
+        state.degraded_mounts.clear();
+        if let Err(e) = state.save() {
+            log::warn!("Failed to update runtime state after teardown: {:#}", e);
+        }
+    }
+
+    let json = serde_json::to_string(&report).context("Failed to serialize teardown report")?;
+
+    println!("{}", json);
+
+    Ok(())
+}
+
+pub fn handle_reload(cli: &Cli, module: &str) -> Result<()> {
     let config = load_config(cli)?;
 
+    utils::validate_module_id(module)?;
+
+    let report = reload::reload_module(&config, module)
+        .with_context(|| format!("Failed to reload module {}", module))?;
+
+    let json = serde_json::to_string(&report).context("Failed to serialize reload report")?;
+
+    println!("{}", json);
+
+    Ok(())
+}
+
+pub fn handle_snapshot(cli: &Cli, action: &SnapshotAction) -> Result<()> {
     match action {
-        "backup-list" => {
+        SnapshotAction::List => {
             let snapshots = granary::list_snapshots()?;
 
             let json = serde_json::to_string(&snapshots)?;
 
             println!("{}", json);
         }
-        "backup-create" => {
-            let reason = value.unwrap_or("Manual Backup");
+        SnapshotAction::Create { reason } => {
+            let config = load_config(cli)?;
 
-            granary::create_snapshot(&config, "Manual Snapshot", reason)?;
+            let id = granary::create_snapshot(&config, "Manual Snapshot", reason)?;
 
-            println!("Snapshot created.");
+            println!("Snapshot {} created.", id);
         }
-        "backup-delete" => {
-            if let Some(id) = value {
-                granary::delete_snapshot(id)?;
+        SnapshotAction::Delete { id } => {
+            granary::delete_snapshot(id)?;
 
-                println!("Snapshot {} deleted.", id);
-            } else {
-                bail!("Missing Snapshot ID");
-            }
+            println!("Snapshot {} deleted.", id);
         }
-        "backup-restore" => {
-            if let Some(id) = value {
-                granary::restore_snapshot(id)?;
+        SnapshotAction::Restore { id } => {
+            granary::restore_snapshot(id)?;
 
-                println!("Snapshot {} restored. Please reboot.", id);
-            } else {
-                bail!("Missing Snapshot ID");
-            }
+            println!("Snapshot {} restored. Please reboot.", id);
         }
-        "granary-list" => handle_system_action(cli, "backup-list", value)?,
-        "granary-create" => handle_system_action(cli, "backup-create", value)?,
-        "granary-delete" => handle_system_action(cli, "backup-delete", value)?,
-        "granary-restore" => handle_system_action(cli, "backup-restore", value)?,
-        _ => bail!("Unknown action: {}", action),
     }
 
     Ok(())
 }
 
+pub fn handle_serve(cli: &Cli, socket: &Path, drift_interval: u64) -> Result<()> {
+    let config = load_config(cli)?;
+
+    utils::init_logging(config.verbose).context("Failed to initialize logging")?;
+
+    rpc::serve(cli, socket, Duration::from_secs(drift_interval))
+}
+
 pub fn handle_poaceae(target_path: &str, action: &PoaceaeAction) -> Result<()> {
     let file = File::open(target_path)
         .with_context(|| format!("Failed to open PoaceaeFS root at {}", target_path))?;
@@ -54,6 +54,23 @@ pub enum DefaultMode {
     Magic,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RollbackPolicy {
+    Never,
+    #[default]
+    Critical,
+    Degraded,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncMode {
+    #[default]
+    Full,
+    Delta,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum MountMode {
@@ -69,14 +86,115 @@ pub struct ModuleRules {
     pub default_mode: MountMode,
     #[serde(default)]
     pub paths: HashMap<String, MountMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+}
+
+fn split_segments(path: &str) -> Vec<&str> {
+    path.split('/')
+        .filter(|s| !s.is_empty() && *s != ".")
+        .collect()
+}
+
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, mark)) = backtrack {
+            p = star + 1;
+            t = mark + 1;
+            backtrack = Some((star, mark + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+fn match_segments(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|i| match_segments(rest, &path[i..])),
+        Some((segment, rest)) => path.split_first().is_some_and(|(head, tail)| {
+            wildcard_match(segment, head) && match_segments(rest, tail)
+        }),
+    }
+}
+
+fn may_match_below(pattern: &[&str], path: &[&str]) -> bool {
+    if path.is_empty() {
+        return !pattern.is_empty();
+    }
+
+    match pattern.split_first() {
+        None => false,
+        Some((&"**", rest)) => may_match_below(rest, path) || may_match_below(pattern, &path[1..]),
+        Some((segment, rest)) => {
+            wildcard_match(segment, path[0]) && may_match_below(rest, &path[1..])
+        }
+    }
+}
+
+// Whether the pattern names the path or one of its parents.
+pub fn pattern_covers(pattern: &str, relative_path: &str) -> bool {
+    let pattern = split_segments(pattern);
+    let path = split_segments(relative_path);
+
+    !pattern.is_empty() && (1..=path.len()).any(|i| match_segments(&pattern, &path[..i]))
+}
+
+// Whether the pattern could name something below the path.
+pub fn pattern_reaches_below(pattern: &str, relative_path: &str) -> bool {
+    may_match_below(&split_segments(pattern), &split_segments(relative_path))
+}
+
+fn rule_specificity(pattern: &str) -> (usize, usize) {
+    let literal = pattern.chars().filter(|c| *c != '*' && *c != '?').count();
+    (literal, pattern.len())
 }
 
 impl ModuleRules {
+    pub fn effective_priority(&self) -> i32 {
+        self.priority.unwrap_or(0)
+    }
+
     pub fn get_mode(&self, relative_path: &str) -> MountMode {
-        if let Some(mode) = self.paths.get(relative_path) {
-            return mode.clone();
+        self.find_rule(relative_path)
+            .map(|(_, mode)| mode.clone())
+            .unwrap_or_else(|| self.default_mode.clone())
+    }
+
+    // A rule covers the path it names and everything below it; the most specific match wins.
+    pub fn find_rule(&self, relative_path: &str) -> Option<(&str, &MountMode)> {
+        if split_segments(relative_path).is_empty() {
+            return None;
         }
-        self.default_mode.clone()
+
+        self.paths
+            .iter()
+            .filter(|(pattern, _)| pattern_covers(pattern, relative_path))
+            .max_by(|(a, _), (b, _)| {
+                rule_specificity(a)
+                    .cmp(&rule_specificity(b))
+                    .then_with(|| b.cmp(a))
+            })
+            .map(|(pattern, mode)| (pattern.as_str(), mode))
+    }
+
+    pub fn has_nested_rules(&self, relative_path: &str) -> bool {
+        self.paths.keys().any(|pattern| {
+            !pattern_covers(pattern, relative_path) && pattern_reaches_below(pattern, relative_path)
+        })
     }
 }
 
@@ -95,10 +213,24 @@ pub struct Config {
     pub disable_umount: bool,
     #[serde(default)]
     pub allow_umount_coexistence: bool,
+    #[serde(default)]
+    pub rollback_policy: RollbackPolicy,
+    #[serde(default)]
+    pub sync_hash: bool,
+    #[serde(default)]
+    pub sync_mode: SyncMode,
+    #[serde(default)]
+    pub sync_include: Vec<String>,
+    #[serde(default)]
+    pub sync_exclude: Vec<String>,
     #[serde(default, alias = "granary")]
     pub backup: BackupConfig,
     #[serde(default = "default_hybrid_mnt_dir")]
     pub hybrid_mnt_dir: String,
+    // Where the offline commands and relabel read the system tree from; boot and reload always
+    // work on `/`.
+    #[serde(default = "default_sysroot")]
+    pub sysroot: PathBuf,
     #[serde(default)]
     pub default_mode: DefaultMode,
     #[serde(default)]
@@ -109,6 +241,10 @@ fn default_hybrid_mnt_dir() -> String {
     defs::DEFAULT_HYBRID_MNT_DIR.to_string()
 }
 
+fn default_sysroot() -> PathBuf {
+    PathBuf::from("/")
+}
+
 fn default_moduledir() -> PathBuf {
     PathBuf::from(defs::MODULES_DIR)
 }
@@ -148,8 +284,14 @@ impl Default for Config {
             overlay_mode: OverlayMode::default(),
             disable_umount: false,
             allow_umount_coexistence: false,
+            rollback_policy: RollbackPolicy::default(),
+            sync_hash: false,
+            sync_mode: SyncMode::default(),
+            sync_include: Vec::new(),
+            sync_exclude: Vec::new(),
             backup: BackupConfig::default(),
             hybrid_mnt_dir: default_hybrid_mnt_dir(),
+            sysroot: default_sysroot(),
             default_mode: DefaultMode::default(),
             rules: HashMap::new(),
         }
@@ -205,3 +347,51 @@ impl Config {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards_match_within_a_segment() {
+        assert!(wildcard_match("*.so", "libfoo.so"));
+        assert!(wildcard_match("lib?.so", "libc.so"));
+        assert!(wildcard_match("a*b*c", "axxbyyc"));
+        assert!(wildcard_match("*", ""));
+        assert!(!wildcard_match("lib?.so", "libcc.so"));
+        assert!(!wildcard_match("*.so", "libfoo.so.1"));
+        assert!(!wildcard_match("a*b", "a"));
+    }
+
+    #[test]
+    fn double_star_spans_any_number_of_segments() {
+        let matches = |pattern: &str, path: &str| {
+            match_segments(&split_segments(pattern), &split_segments(path))
+        };
+
+        assert!(matches("system/**/*.so", "system/lib.so"));
+        assert!(matches("system/**/*.so", "system/lib64/hw/foo.so"));
+        assert!(matches("system/*/hosts", "system/etc/hosts"));
+        assert!(matches("**", "system/etc"));
+        assert!(!matches("system/*/hosts", "system/etc/extra/hosts"));
+        assert!(!matches("system/etc", "system/etc/hosts"));
+        assert!(!matches("vendor/**", "system/etc"));
+    }
+
+    #[test]
+    fn most_specific_rule_wins() {
+        let rules = ModuleRules {
+            paths: HashMap::from([
+                ("system/etc".to_string(), MountMode::Magic),
+                ("system/etc/*.conf".to_string(), MountMode::Ignore),
+            ]),
+            ..Default::default()
+        };
+
+        assert_eq!(rules.get_mode("system/etc/hosts"), MountMode::Magic);
+        assert_eq!(rules.get_mode("system/etc/gps.conf"), MountMode::Ignore);
+        assert_eq!(rules.get_mode("system/bin/sh"), MountMode::Overlay);
+        assert!(rules.has_nested_rules("system"));
+        assert!(!rules.has_nested_rules("vendor"));
+    }
+}
@@ -4,3 +4,4 @@
 pub mod cli;
 pub mod cli_handlers;
 pub mod config;
+pub mod rpc;
@@ -0,0 +1,436 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, Result, bail};
+use nix::sys::stat::{Mode, umask};
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::{Value, json};
+
+use crate::{
+    conf::{
+        cli::Cli,
+        cli_handlers,
+        config::{Config, ModuleRules},
+    },
+    core::{
+        error::ErrorKind,
+        events::{self, Event},
+        inventory::{
+            lifecycle::{self, LifecycleAction},
+            model as modules,
+        },
+        ops::{backup as granary, reload, verify},
+        state::RuntimeState,
+    },
+    utils,
+};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+// Anything the daemon itself fails at; `data` carries the same kind and exit code the CLI uses.
+const APPLICATION_ERROR: i64 = -32000;
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+    #[serde(default)]
+    id: Option<Value>,
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+    data: Option<Value>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let mut error = json!({ "code": self.code, "message": self.message });
+        if let Some(data) = &self.data {
+            error["data"] = data.clone();
+        }
+        error
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(error: anyhow::Error) -> Self {
+        let kind = ErrorKind::classify(&error);
+        Self {
+            code: APPLICATION_ERROR,
+            message: format!("{:#}", error),
+            data: Some(json!({ "kind": kind, "exit_code": kind.exit_code() })),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ConfigSaveParams {
+    config: Config,
+}
+
+#[derive(Deserialize)]
+struct ModuleRulesParams {
+    module: String,
+    rules: ModuleRules,
+}
+
+#[derive(Deserialize)]
+struct ModuleSetParams {
+    id: String,
+    action: LifecycleAction,
+    #[serde(default)]
+    snapshot: bool,
+}
+
+#[derive(Deserialize)]
+struct IdParams {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct ConflictsParams {
+    #[serde(default)]
+    duplicates: bool,
+}
+
+#[derive(Deserialize)]
+struct SnapshotCreateParams {
+    #[serde(default = "default_snapshot_reason")]
+    reason: String,
+}
+
+fn default_snapshot_reason() -> String {
+    "Manual Backup".to_string()
+}
+
+#[derive(Deserialize)]
+struct StatusParams {
+    #[serde(default)]
+    verify: bool,
+    #[serde(default)]
+    pid: Option<i32>,
+}
+
+// Omitted params are read as an empty object so methods whose fields all have defaults can be
+// called without any.
+fn params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+fn to_value(value: impl serde::Serialize) -> Result<Value, RpcError> {
+    Ok(serde_json::to_value(value).context("Failed to serialize response")?)
+}
+
+fn send(writer: &Mutex<UnixStream>, message: &Value) -> std::io::Result<()> {
+    let mut line = message.to_string();
+    line.push('\n');
+    writer
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .write_all(line.as_bytes())
+}
+
+struct Server<'a> {
+    cli: &'a Cli,
+    // Held by every method that changes config, modules or mounts so two clients never
+    // interleave them.
+    busy: Mutex<()>,
+}
+
+struct Connection {
+    writer: Arc<Mutex<UnixStream>>,
+    subscription: Option<u64>,
+}
+
+impl Server<'_> {
+    fn lock(&self) -> MutexGuard<'_, ()> {
+        self.busy.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn config(&self) -> Result<Config, RpcError> {
+        Ok(cli_handlers::load_config(self.cli)?)
+    }
+
+    fn dispatch(&self, conn: &mut Connection, method: &str, raw: Value) -> Result<Value, RpcError> {
+        match method {
+            "config.get" => to_value(self.config()?),
+            "config.save" => {
+                let p: ConfigSaveParams = params(raw)?;
+                let _busy = self.lock();
+                cli_handlers::save_config(self.cli, &p.config)?;
+                Ok(Value::Null)
+            }
+            "config.save_module_rules" => {
+                let p: ModuleRulesParams = params(raw)?;
+                let _busy = self.lock();
+                cli_handlers::save_module_rules(&p.module, p.rules)?;
+                Ok(Value::Null)
+            }
+            "modules.list" => to_value(modules::list(&self.config()?)?),
+            "modules.set" => {
+                let p: ModuleSetParams = params(raw)?;
+                let config = self.config()?;
+                let _busy = self.lock();
+                to_value(lifecycle::apply(&config, &p.id, p.action, p.snapshot)?)
+            }
+            "modules.reload" => {
+                let p: IdParams = params(raw)?;
+                utils::validate_module_id(&p.id)?;
+                let config = self.config()?;
+                let _busy = self.lock();
+                to_value(reload::reload_module(&config, &p.id)?)
+            }
+            "conflicts" => {
+                let p: ConflictsParams = params(raw)?;
+                to_value(cli_handlers::conflicts(&self.config()?, p.duplicates)?)
+            }
+            "diagnostics" => to_value(cli_handlers::diagnostics(&self.config()?)?),
+            "snapshots.list" => to_value(granary::list_snapshots()?),
+            "snapshots.create" => {
+                let p: SnapshotCreateParams = params(raw)?;
+                let config = self.config()?;
+                let _busy = self.lock();
+                let id = granary::create_snapshot(&config, "Manual Snapshot", &p.reason)?;
+                Ok(json!({ "id": id }))
+            }
+            "snapshots.delete" => {
+                let p: IdParams = params(raw)?;
+                let _busy = self.lock();
+                granary::delete_snapshot(&p.id)?;
+                Ok(Value::Null)
+            }
+            "snapshots.restore" => {
+                let p: IdParams = params(raw)?;
+                let _busy = self.lock();
+                granary::restore_snapshot(&p.id)?;
+                Ok(Value::Null)
+            }
+            "status" => {
+                let p: StatusParams = params(raw)?;
+                if p.verify {
+                    to_value(verify::verify(p.pid)?)
+                } else {
+                    to_value(RuntimeState::load().context("Failed to load runtime state")?)
+                }
+            }
+            "events.publish" => {
+                events::emit(params(raw)?);
+                Ok(Value::Null)
+            }
+            "events.subscribe" => {
+                let id = match conn.subscription {
+                    Some(id) => id,
+                    None => {
+                        let id = forward_events(conn.writer.clone());
+                        conn.subscription = Some(id);
+                        id
+                    }
+                };
+                Ok(json!({ "subscription": id }))
+            }
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("Unknown method: {}", method),
+            )),
+        }
+    }
+
+    // One request per line; notifications (requests without an id) are run but not answered.
+    fn handle_line(&self, conn: &mut Connection, line: &str) -> Option<Value> {
+        let value: Value = match serde_json::from_str(line) {
+            Ok(value) => value,
+            Err(e) => {
+                let error = RpcError::new(PARSE_ERROR, e.to_string());
+                return Some(json!({ "jsonrpc": "2.0", "id": null, "error": error.to_json() }));
+            }
+        };
+
+        let request = match serde_json::from_value::<Request>(value.clone()) {
+            Ok(request) if request.jsonrpc == "2.0" => request,
+            _ => {
+                let error = RpcError::new(INVALID_REQUEST, "Invalid JSON-RPC 2.0 request");
+                let id = value.get("id").cloned().unwrap_or(Value::Null);
+                return Some(json!({ "jsonrpc": "2.0", "id": id, "error": error.to_json() }));
+            }
+        };
+
+        log::debug!("RPC: {}", request.method);
+        let result = self.dispatch(conn, &request.method, request.params);
+        if let Err(e) = &result {
+            log::warn!("RPC {} failed: {}", request.method, e.message);
+        }
+
+        let id = request.id?;
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(e) => json!({ "jsonrpc": "2.0", "id": id, "error": e.to_json() }),
+        })
+    }
+
+    fn handle_client(&self, stream: UnixStream) -> Result<()> {
+        let mut conn = Connection {
+            writer: Arc::new(Mutex::new(stream.try_clone()?)),
+            subscription: None,
+        };
+
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(response) = self.handle_line(&mut conn, &line)
+                && send(&conn.writer, &response).is_err()
+            {
+                break;
+            }
+        }
+
+        if let Some(id) = conn.subscription {
+            events::unsubscribe(id);
+        }
+
+        Ok(())
+    }
+}
+
+// Pushes every event to the client as an `event` notification until it goes away.
+fn forward_events(writer: Arc<Mutex<UnixStream>>) -> u64 {
+    let (id, rx) = events::subscribe();
+
+    thread::spawn(move || {
+        for event in rx {
+            let message = json!({ "jsonrpc": "2.0", "method": "event", "params": event });
+            if send(&writer, &message).is_err() {
+                break;
+            }
+        }
+        events::unsubscribe(id);
+    });
+
+    id
+}
+
+// Reports mount drift as it appears and clears, rather than on every poll.
+fn watch_drift(interval: Duration) {
+    let mut last = None;
+
+    loop {
+        thread::sleep(interval);
+
+        let report = match verify::verify(None) {
+            Ok(report) => report,
+            Err(e) => {
+                log::debug!("Drift check skipped: {:#}", e);
+                continue;
+            }
+        };
+
+        let summary = (
+            report.healthy,
+            report.present,
+            report.missing,
+            report.shadowed,
+            report.wrong_type,
+        );
+        if last.is_none_or(|last| last != summary) && (last.is_some() || !report.healthy) {
+            if !report.healthy {
+                log::warn!(
+                    "Mount drift: {} missing, {} shadowed, {} wrong type",
+                    report.missing,
+                    report.shadowed,
+                    report.wrong_type
+                );
+            }
+            events::emit(Event::Drift {
+                healthy: report.healthy,
+                present: report.present,
+                missing: report.missing,
+                shadowed: report.shadowed,
+                wrong_type: report.wrong_type,
+            });
+        }
+        last = Some(summary);
+    }
+}
+
+// A socket file nobody answers on is left over from a daemon that died; a live one means
+// another instance is already serving.
+fn bind(socket: &Path) -> Result<UnixListener> {
+    if socket.exists() {
+        if UnixStream::connect(socket).is_ok() {
+            bail!("Another daemon is already serving on {}", socket.display());
+        }
+        fs::remove_file(socket)
+            .with_context(|| format!("Failed to remove stale socket {}", socket.display()))?;
+    }
+
+    if let Some(parent) = socket.parent() {
+        utils::ensure_dir_exists(parent)?;
+    }
+
+    // Created under a restrictive umask so the socket is never reachable by other users.
+    let old_mask = umask(Mode::from_bits_truncate(0o177));
+    let listener = UnixListener::bind(socket);
+    umask(old_mask);
+
+    listener.with_context(|| format!("Failed to bind {}", socket.display()))
+}
+
+pub fn serve(cli: &Cli, socket: &Path, drift_interval: Duration) -> Result<()> {
+    let listener = bind(socket)?;
+    let server = Server {
+        cli,
+        busy: Mutex::new(()),
+    };
+
+    log::info!(">> Serving control API on {}", socket.display());
+
+    events::serve_locally();
+
+    if !drift_interval.is_zero() {
+        thread::spawn(move || watch_drift(drift_interval));
+    }
+
+    thread::scope(|scope| {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let server = &server;
+                    scope.spawn(move || {
+                        if let Err(e) = server.handle_client(stream) {
+                            log::debug!("Control client disconnected: {:#}", e);
+                        }
+                    });
+                }
+                Err(e) => log::warn!("Failed to accept control connection: {}", e),
+            }
+        }
+    });
+
+    Ok(())
+}
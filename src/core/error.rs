@@ -0,0 +1,106 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{fmt, io};
+
+use serde::Serialize;
+
+use crate::core::ops::transaction::StepFailure;
+
+// Exit codes are part of the CLI contract with the WebUI and scripts; never renumber them.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    Internal,
+    ConfigInvalid,
+    ModuleNotFound,
+    SnapshotMissing,
+    MountFailure,
+    PermissionDenied,
+}
+
+impl ErrorKind {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::Internal => 1,
+            Self::ConfigInvalid => 10,
+            Self::ModuleNotFound => 11,
+            Self::SnapshotMissing => 12,
+            Self::MountFailure => 13,
+            Self::PermissionDenied => 14,
+        }
+    }
+
+    // The first cause in the chain that carries a kind decides it, so context added on the
+    // way up never hides the original classification.
+    pub fn classify(error: &anyhow::Error) -> Self {
+        for cause in error.chain() {
+            if let Some(e) = cause.downcast_ref::<TypedError>() {
+                return e.kind;
+            }
+            if cause.is::<StepFailure>() {
+                return Self::MountFailure;
+            }
+            if cause.is::<toml::de::Error>() {
+                return Self::ConfigInvalid;
+            }
+            if let Some(e) = cause.downcast_ref::<io::Error>()
+                && e.kind() == io::ErrorKind::PermissionDenied
+            {
+                return Self::PermissionDenied;
+            }
+            if let Some(errno) = cause.downcast_ref::<rustix::io::Errno>()
+                && (*errno == rustix::io::Errno::PERM || *errno == rustix::io::Errno::ACCESS)
+            {
+                return Self::PermissionDenied;
+            }
+        }
+
+        Self::Internal
+    }
+}
+
+#[derive(Debug)]
+pub struct TypedError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl TypedError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for TypedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for TypedError {}
+
+#[derive(Serialize)]
+struct ErrorJson {
+    code: ErrorKind,
+    message: String,
+}
+
+// Prints the error as `{code, message}` JSON on stderr and returns the exit code to use.
+pub fn report(error: &anyhow::Error) -> i32 {
+    let kind = ErrorKind::classify(error);
+    let json = ErrorJson {
+        code: kind,
+        message: format!("{:#}", error),
+    };
+
+    match serde_json::to_string(&json) {
+        Ok(json) => eprintln!("{}", json),
+        Err(_) => eprintln!("{:#}", error),
+    }
+
+    kind.exit_code()
+}
@@ -0,0 +1,130 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    io::Write,
+    os::unix::net::UnixStream,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender},
+    },
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{core::state::DegradedMount, defs};
+
+static SUBSCRIBERS: Mutex<Vec<(u64, Sender<Event>)>> = Mutex::new(Vec::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+static SERVER: Mutex<Server> = Mutex::new(Server::Unknown);
+
+// How events leave a process that is not the control daemon itself.
+enum Server {
+    Unknown,
+    Connected(UnixStream),
+    Unavailable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncOutcome {
+    Synced,
+    Unchanged,
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    SyncProgress {
+        module: String,
+        outcome: SyncOutcome,
+        done: usize,
+        total: usize,
+    },
+    MountResult {
+        overlay_modules: Vec<String>,
+        magic_modules: Vec<String>,
+        degraded_mounts: Vec<DegradedMount>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        failed_step: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reason: Option<String>,
+    },
+    Drift {
+        healthy: bool,
+        present: usize,
+        missing: usize,
+        shadowed: usize,
+        wrong_type: usize,
+    },
+}
+
+// Every event emitted from now on is delivered to the returned receiver until `unsubscribe`.
+pub fn subscribe() -> (u64, Receiver<Event>) {
+    let (tx, rx) = mpsc::channel();
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+    SUBSCRIBERS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push((id, tx));
+
+    (id, rx)
+}
+
+pub fn unsubscribe(id: u64) {
+    SUBSCRIBERS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .retain(|(sub, _)| *sub != id);
+}
+
+// Called once by the control daemon, which delivers events to its own subscribers instead of
+// forwarding them to itself.
+pub fn serve_locally() {
+    *SERVER.lock().unwrap_or_else(|e| e.into_inner()) = Server::Unavailable;
+}
+
+// Other processes, such as the boot sequence, publish to the control daemon when one is
+// running. The socket is tried once per process, and a daemon that stops reading is dropped
+// rather than allowed to stall the caller.
+fn forward(event: &Event) {
+    let mut server = SERVER.lock().unwrap_or_else(|e| e.into_inner());
+
+    if matches!(*server, Server::Unknown) {
+        *server = match UnixStream::connect(defs::CONTROL_SOCKET) {
+            Ok(stream)
+                if stream
+                    .set_write_timeout(Some(Duration::from_secs(1)))
+                    .is_ok() =>
+            {
+                Server::Connected(stream)
+            }
+            _ => Server::Unavailable,
+        };
+    }
+
+    if let Server::Connected(stream) = &mut *server {
+        let message = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "events.publish",
+            "params": event,
+        });
+        if writeln!(stream, "{}", message).is_err() {
+            log::debug!("Control daemon stopped taking events");
+            *server = Server::Unavailable;
+        }
+    }
+}
+
+pub fn emit(event: Event) {
+    forward(&event);
+
+    let mut subscribers = SUBSCRIBERS.lock().unwrap_or_else(|e| e.into_inner());
+
+    subscribers.retain(|(_, tx)| tx.send(event.clone()).is_ok());
+}
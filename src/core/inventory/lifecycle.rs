@@ -0,0 +1,149 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    conf::config::Config,
+    core::{
+        error::{ErrorKind, TypedError},
+        ops::backup as granary,
+        state::RuntimeState,
+    },
+    defs, utils,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LifecycleAction {
+    Enable,
+    Disable,
+    Remove,
+    SkipMount,
+    UnskipMount,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApplyHint {
+    None,
+    Reload,
+    Reboot,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MarkerState {
+    pub disable: bool,
+    pub remove: bool,
+    pub skip_mount: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LifecycleReport {
+    pub module: String,
+    pub action: LifecycleAction,
+    pub changed: bool,
+    pub markers: MarkerState,
+    pub snapshot: Option<String>,
+    pub apply: ApplyHint,
+}
+
+fn markers(module_path: &Path) -> MarkerState {
+    MarkerState {
+        disable: module_path.join(defs::DISABLE_FILE_NAME).exists(),
+        remove: module_path.join(defs::REMOVE_FILE_NAME).exists(),
+        skip_mount: module_path.join(defs::SKIP_MOUNT_FILE_NAME).exists(),
+    }
+}
+
+fn set_marker(module_path: &Path, name: &str, present: bool) -> Result<bool> {
+    let marker = module_path.join(name);
+
+    match (present, marker.exists()) {
+        (true, false) => fs::write(&marker, "")
+            .with_context(|| format!("Failed to create {}", marker.display()))?,
+        (false, true) => fs::remove_file(&marker)
+            .with_context(|| format!("Failed to remove {}", marker.display()))?,
+        _ => return Ok(false),
+    }
+
+    Ok(true)
+}
+
+// Marker changes reach the mounts through a hot reload unless nothing is mounted yet or the
+// storage is a read-only EROFS image, which only a reboot can rebuild.
+fn apply_hint(changed: bool) -> ApplyHint {
+    if !changed {
+        return ApplyHint::None;
+    }
+
+    match RuntimeState::load() {
+        Ok(state) if !state.mount_point.as_os_str().is_empty() && state.storage_mode != "erofs" => {
+            ApplyHint::Reload
+        }
+        _ => ApplyHint::Reboot,
+    }
+}
+
+pub fn apply(
+    config: &Config,
+    module_id: &str,
+    action: LifecycleAction,
+    snapshot: bool,
+) -> Result<LifecycleReport> {
+    utils::validate_module_id(module_id)?;
+
+    let module_path = config.moduledir.join(module_id);
+    if !module_path.is_dir() {
+        bail!(TypedError::new(
+            ErrorKind::ModuleNotFound,
+            format!(
+                "Module {} not found in {}",
+                module_id,
+                config.moduledir.display()
+            )
+        ));
+    }
+
+    let snapshot = if snapshot {
+        Some(granary::create_snapshot(
+            config,
+            "Module Lifecycle",
+            &format!("Before {:?} {}", action, module_id),
+        )?)
+    } else {
+        None
+    };
+
+    let changed = match action {
+        LifecycleAction::Enable => {
+            let enabled = set_marker(&module_path, defs::DISABLE_FILE_NAME, false)?;
+            set_marker(&module_path, defs::REMOVE_FILE_NAME, false)? || enabled
+        }
+        LifecycleAction::Disable => set_marker(&module_path, defs::DISABLE_FILE_NAME, true)?,
+        LifecycleAction::Remove => set_marker(&module_path, defs::REMOVE_FILE_NAME, true)?,
+        LifecycleAction::SkipMount => set_marker(&module_path, defs::SKIP_MOUNT_FILE_NAME, true)?,
+        LifecycleAction::UnskipMount => {
+            set_marker(&module_path, defs::SKIP_MOUNT_FILE_NAME, false)?
+        }
+    };
+
+    log::info!(
+        "Module {}: {:?} ({})",
+        module_id,
+        action,
+        if changed { "changed" } else { "unchanged" }
+    );
+
+    Ok(LifecycleReport {
+        module: module_id.to_string(),
+        action,
+        changed,
+        markers: markers(&module_path),
+        snapshot,
+        apply: apply_hint(changed),
+    })
+}
@@ -2,79 +2,215 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use std::{
-    collections::HashSet,
+    collections::{BTreeMap, HashSet},
     fs::{self},
     io::{BufRead, BufReader},
     os::unix::fs::{FileTypeExt, MetadataExt},
     path::{Path, PathBuf},
-    sync::OnceLock,
 };
 
 use anyhow::Result;
 #[cfg(any(target_os = "linux", target_os = "android"))]
 use extattr::lgetxattr;
-use regex_lite::Regex;
 use serde::Serialize;
 
 use super::scanner as inventory;
 use crate::{
     conf::config::{self, MountMode},
-    core::state::RuntimeState,
+    core::state::{ModuleStats, RuntimeState},
     defs, utils,
 };
 
-static MODULE_PROP_REGEX: OnceLock<Regex> = OnceLock::new();
-
 #[derive(Default)]
 struct ModuleProp {
+    id: String,
     name: String,
     version: String,
+    version_code: Option<i64>,
     author: String,
     description: String,
+    update_json: Option<String>,
+    extra: BTreeMap<String, String>,
+    warnings: Vec<String>,
 }
 
-impl From<&Path> for ModuleProp {
-    fn from(path: &Path) -> Self {
-        let mut prop = ModuleProp::default();
-        let re = MODULE_PROP_REGEX.get_or_init(|| {
-            Regex::new(r"^([a-zA-Z0-9_.]+)=(.*)$").expect("Failed to compile module prop regex")
-        });
-
-        if let Ok(file) = fs::File::open(path) {
-            for line in BufReader::new(file).lines().map_while(Result::ok) {
-                if let Some(caps) = re.captures(line.trim()) {
-                    let k = caps.get(1).map_or("", |m| m.as_str());
-                    let v = caps.get(2).map_or("", |m| m.as_str());
-
-                    match k {
-                        "name" => prop.name = v.to_string(),
-                        "version" => prop.version = v.to_string(),
-                        "author" => prop.author = v.to_string(),
-                        "description" => prop.description = v.to_string(),
-                        _ => {}
+// Joins continuation lines the way Java properties (and KernelSU) do: a line ending in an odd
+// number of backslashes continues on the next one, minus that line's leading whitespace.
+fn logical_lines(content: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current: Option<String> = None;
+
+    for raw in content.lines() {
+        let line = match current {
+            Some(_) => raw.trim_start(),
+            None => {
+                let trimmed = raw.trim_start();
+                if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with('!') {
+                    continue;
+                }
+                trimmed
+            }
+        };
+
+        let trailing = line.len() - line.trim_end_matches('\\').len();
+        let buffer = current.get_or_insert_with(String::new);
+        if trailing % 2 == 1 {
+            buffer.push_str(&line[..line.len() - 1]);
+        } else {
+            buffer.push_str(line);
+            lines.extend(current.take());
+        }
+    }
+    lines.extend(current);
+
+    lines
+}
+
+fn unescape(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('f') => out.push('\u{c}'),
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                    Some(decoded) => out.push(decoded),
+                    None => {
+                        out.push_str("\\u");
+                        out.push_str(&hex);
                     }
                 }
             }
+            Some(other) => out.push(other),
+            None => {}
         }
+    }
+
+    out
+}
+
+// Splits at the first unescaped `=`, `:` or whitespace; the separator may be padded.
+fn split_entry(line: &str) -> (String, String) {
+    let mut escaped = false;
+    let mut key_end = line.len();
+
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '=' || c == ':' || c.is_whitespace() {
+            key_end = i;
+            break;
+        }
+    }
+
+    let rest = line[key_end..].trim_start();
+    let rest = rest
+        .strip_prefix('=')
+        .or_else(|| rest.strip_prefix(':'))
+        .unwrap_or(rest)
+        .trim_start();
+
+    (unescape(&line[..key_end]), unescape(rest))
+}
+
+impl ModuleProp {
+    fn parse(content: &str) -> Self {
+        let mut prop = ModuleProp::default();
+        let mut seen = HashSet::new();
+
+        for line in logical_lines(content) {
+            let (key, value) = split_entry(&line);
+            if !seen.insert(key.clone()) {
+                prop.warnings
+                    .push(format!("Duplicate key '{}', the last value wins", key));
+            }
+
+            match key.as_str() {
+                "id" => prop.id = value,
+                "name" => prop.name = value,
+                "version" => prop.version = value,
+                "versionCode" => match value.trim().parse() {
+                    Ok(code) => prop.version_code = Some(code),
+                    Err(_) => prop
+                        .warnings
+                        .push(format!("versionCode '{}' is not an integer", value)),
+                },
+                "author" => prop.author = value,
+                "description" => prop.description = value,
+                "updateJson" => prop.update_json = Some(value),
+                _ => {
+                    prop.extra.insert(key, value);
+                }
+            }
+        }
+
+        prop
+    }
+
+    fn load(module_dir: &Path, dir_name: &str) -> Self {
+        let path = module_dir.join("module.prop");
+        let mut prop = match fs::read_to_string(&path) {
+            Ok(content) => Self::parse(&content),
+            Err(e) => Self {
+                warnings: vec![format!("Failed to read module.prop: {}", e)],
+                ..Default::default()
+            },
+        };
+
+        if prop.id.is_empty() {
+            prop.warnings.push("module.prop has no id".to_string());
+        } else if prop.id != dir_name {
+            prop.warnings.push(format!(
+                "module.prop id '{}' does not match directory '{}'",
+                prop.id, dir_name
+            ));
+        } else if let Err(e) = utils::validate_module_id(&prop.id) {
+            prop.warnings.push(e.to_string());
+        }
+
+        for warning in &prop.warnings {
+            log::warn!("Module {}: {}", dir_name, warning);
+        }
+
         prop
     }
 }
 
 #[derive(Serialize)]
-struct ModuleInfo {
+pub struct ModuleInfo {
     id: String,
     name: String,
     version: String,
+    version_code: Option<i64>,
     author: String,
     description: String,
+    update_json: Option<String>,
+    extra_props: BTreeMap<String, String>,
+    prop_warnings: Vec<String>,
     mode: String,
     is_mounted: bool,
+    storage: Option<ModuleStats>,
     rules: config::ModuleRules,
 }
 
 impl ModuleInfo {
-    fn new(m: inventory::Module, mounted_set: &HashSet<&str>) -> Self {
-        let prop = ModuleProp::from(m.source_path.join("module.prop").as_path());
+    fn new(
+        m: inventory::Module,
+        mounted_set: &HashSet<&str>,
+        stats: &BTreeMap<String, ModuleStats>,
+    ) -> Self {
+        let prop = ModuleProp::load(&m.source_path, &m.id);
 
         let mode_str = match m.rules.default_mode {
             MountMode::Overlay => "auto",
@@ -84,11 +220,16 @@ impl ModuleInfo {
 
         Self {
             is_mounted: mounted_set.contains(m.id.as_str()),
+            storage: stats.get(&m.id).cloned(),
             id: m.id,
             name: prop.name,
             version: prop.version,
+            version_code: prop.version_code,
             author: prop.author,
             description: prop.description,
+            update_json: prop.update_json,
+            extra_props: prop.extra,
+            prop_warnings: prop.warnings,
             mode: mode_str.to_string(),
             rules: m.rules,
         }
@@ -122,7 +263,7 @@ impl ModuleFile {
             }
 
             #[cfg(any(target_os = "linux", target_os = "android"))]
-            if let Ok(val) = lgetxattr(&real_path, defs::REPLACE_DIR_XATTR) {
+            if let Ok(val) = lgetxattr(&real_path, crate::sys::probe::opaque_xattr()) {
                 return String::from_utf8_lossy(&val) == "y";
             }
 
@@ -148,7 +289,7 @@ impl ModuleFile {
     }
 }
 
-pub fn print_list(config: &config::Config) -> Result<()> {
+pub fn list(config: &config::Config) -> Result<Vec<ModuleInfo>> {
     let modules = inventory::scan(&config.moduledir, config)?;
 
     let state = RuntimeState::load().unwrap_or_default();
@@ -160,14 +301,10 @@ pub fn print_list(config: &config::Config) -> Result<()> {
         .map(|s| s.as_str())
         .collect();
 
-    let infos: Vec<ModuleInfo> = modules
+    Ok(modules
         .into_iter()
-        .map(|m| ModuleInfo::new(m, &mounted_ids))
-        .collect();
-
-    println!("{}", serde_json::to_string(&infos)?);
-
-    Ok(())
+        .map(|m| ModuleInfo::new(m, &mounted_ids, &state.module_stats))
+        .collect())
 }
 
 pub fn update_description(storage_mode: &str, overlay_count: usize, magic_count: usize) {
@@ -214,3 +351,49 @@ pub fn update_description(storage_mode: &str, overlay_count: usize, magic_count:
         log::warn!("Failed to update module description: {}", e);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_known_keys_and_keeps_the_rest() {
+        let prop = ModuleProp::parse(
+            "# comment\nid=demo\nname = Demo Module\nversion:v1.0\nversionCode=42\n\
+             author Someone\nupdateJson=https://example.com/u.json\ncustom=value\n",
+        );
+
+        assert_eq!(prop.id, "demo");
+        assert_eq!(prop.name, "Demo Module");
+        assert_eq!(prop.version, "v1.0");
+        assert_eq!(prop.version_code, Some(42));
+        assert_eq!(prop.author, "Someone");
+        assert_eq!(
+            prop.update_json.as_deref(),
+            Some("https://example.com/u.json")
+        );
+        assert_eq!(prop.extra.get("custom").map(String::as_str), Some("value"));
+        assert!(prop.warnings.is_empty());
+    }
+
+    #[test]
+    fn joins_continuations_and_unescapes() {
+        let prop = ModuleProp::parse(
+            "description=first \\\n    second\nname=a\\=b\\tc\nauthor=\\u00e9\nversion=ends\\\\\n",
+        );
+
+        assert_eq!(prop.description, "first second");
+        assert_eq!(prop.name, "a=b\tc");
+        assert_eq!(prop.author, "é");
+        assert_eq!(prop.version, "ends\\");
+    }
+
+    #[test]
+    fn warns_about_duplicates_and_bad_version_codes() {
+        let prop = ModuleProp::parse("id=one\nid=two\nversionCode=abc\n");
+
+        assert_eq!(prop.id, "two");
+        assert_eq!(prop.version_code, None);
+        assert_eq!(prop.warnings.len(), 2);
+    }
+}
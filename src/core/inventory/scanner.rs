@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use std::{
+    cmp::Ordering,
     collections::HashMap,
     fs,
     path::{Path, PathBuf},
@@ -29,6 +30,7 @@ fn load_module_rules(module_dir: &Path, module_id: &str, cfg: &config::Config) -
     struct PartialRules {
         default_mode: Option<MountMode>,
         paths: Option<HashMap<String, MountMode>>,
+        priority: Option<i32>,
     }
 
     let internal_config = module_dir.join("hybrid_rules.json");
@@ -43,6 +45,9 @@ fn load_module_rules(module_dir: &Path, module_id: &str, cfg: &config::Config) -
                     if let Some(paths) = partial.paths {
                         rules.paths = paths;
                     }
+                    if partial.priority.is_some() {
+                        rules.priority = partial.priority;
+                    }
                 }
                 Err(e) => {
                     log::warn!("Failed to parse rules for module '{}': {}", module_id, e)
@@ -55,6 +60,9 @@ fn load_module_rules(module_dir: &Path, module_id: &str, cfg: &config::Config) -
     if let Some(global_rules) = cfg.rules.get(module_id) {
         rules.default_mode = global_rules.default_mode.clone();
         rules.paths.extend(global_rules.paths.clone());
+        if global_rules.priority.is_some() {
+            rules.priority = global_rules.priority;
+        }
     }
 
     rules
@@ -109,7 +117,15 @@ pub fn scan(source_dir: &Path, cfg: &config::Config) -> Result<Vec<Module>> {
         })
         .collect();
 
-    modules.sort_by(|a, b| b.id.cmp(&a.id));
+    modules.sort_by(compare_precedence);
 
     Ok(modules)
 }
+
+// Higher priority wins; equal priorities fall back to reverse ID order.
+pub fn compare_precedence(a: &Module, b: &Module) -> Ordering {
+    b.rules
+        .effective_priority()
+        .cmp(&a.rules.effective_priority())
+        .then_with(|| b.id.cmp(&a.id))
+}
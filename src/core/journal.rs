@@ -0,0 +1,194 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    collections::HashSet,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use procfs::process::Process;
+use rustix::mount::{UnmountFlags, unmount};
+use serde::{Deserialize, Serialize};
+
+use crate::{defs, utils};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MountRecord {
+    pub step: String,
+    pub mount_id: i32,
+    pub mount_point: PathBuf,
+    pub fs_type: String,
+    #[serde(default)]
+    pub modules: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Presence {
+    Gone,
+    Covered,
+    Topmost,
+}
+
+impl MountRecord {
+    // Unmounting by path detaches whatever sits on top there, so a record may only be unmounted
+    // while its mount is still at the recorded point and nothing has been stacked on it since.
+    pub fn presence(&self) -> Result<Presence> {
+        let mounts = Process::myself()?
+            .mountinfo()
+            .context("Failed to read mountinfo")?
+            .0;
+        let mut here = mounts.iter().filter(|m| m.mount_point == self.mount_point);
+
+        Ok(if !here.clone().any(|m| m.mnt_id == self.mount_id) {
+            Presence::Gone
+        } else if here.any(|m| m.pid == self.mount_id) {
+            Presence::Covered
+        } else {
+            Presence::Topmost
+        })
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct TeardownReport {
+    pub unmounted: Vec<PathBuf>,
+    pub skipped: Vec<PathBuf>,
+    pub covered: Vec<PathBuf>,
+    pub failed: Vec<PathBuf>,
+}
+
+pub fn reset() -> Result<()> {
+    let path = Path::new(defs::MOUNT_JOURNAL_FILE);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, "").context("Failed to reset mount journal")
+}
+
+fn encode(records: &[MountRecord]) -> Result<String> {
+    let mut buffer = String::new();
+    for record in records {
+        buffer.push_str(&serde_json::to_string(record)?);
+        buffer.push('\n');
+    }
+    Ok(buffer)
+}
+
+pub fn append(records: &[MountRecord]) -> Result<()> {
+    if records.is_empty() {
+        return Ok(());
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(defs::MOUNT_JOURNAL_FILE)
+        .context("Failed to open mount journal")?;
+
+    file.write_all(encode(records)?.as_bytes())?;
+    Ok(())
+}
+
+pub fn load() -> Result<Vec<MountRecord>> {
+    let path = Path::new(defs::MOUNT_JOURNAL_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(path).context("Failed to read mount journal")?;
+
+    Ok(content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(record) => Some(record),
+            Err(e) => {
+                log::warn!("Skipping malformed journal entry: {}", e);
+                None
+            }
+        })
+        .collect())
+}
+
+fn rewrite(records: &[MountRecord]) -> Result<()> {
+    utils::atomic_write(defs::MOUNT_JOURNAL_FILE, encode(records)?)
+}
+
+pub fn forget(mount_ids: &HashSet<i32>) -> Result<()> {
+    if mount_ids.is_empty() {
+        return Ok(());
+    }
+
+    let mut records = load()?;
+    records.retain(|r| !mount_ids.contains(&r.mount_id));
+    rewrite(&records)
+}
+
+pub fn teardown() -> Result<TeardownReport> {
+    detach(|_| true)
+}
+
+// Journal order is mount order, so walking it backwards detaches children before the
+// mounts they sit on. A record is only acted on while its mount ID still lives at the
+// recorded mount point; anything else was already unmounted or replaced. Mounts someone else
+// has stacked on stay journaled, as they are still ours underneath.
+pub fn detach(select: impl Fn(&MountRecord) -> bool) -> Result<TeardownReport> {
+    let records = load()?;
+
+    let mut report = TeardownReport::default();
+    let mut remaining = Vec::new();
+
+    for record in records.into_iter().rev() {
+        if !select(&record) {
+            remaining.push(record);
+            continue;
+        }
+
+        match record.presence()? {
+            Presence::Gone => {
+                report.skipped.push(record.mount_point);
+                continue;
+            }
+            Presence::Covered => {
+                log::warn!(
+                    "Teardown: {} ({}) is covered by another mount, leaving it",
+                    record.mount_point.display(),
+                    record.step
+                );
+                report.covered.push(record.mount_point.clone());
+                remaining.push(record);
+                continue;
+            }
+            Presence::Topmost => {}
+        }
+
+        match unmount(&record.mount_point, UnmountFlags::DETACH) {
+            Ok(()) => {
+                log::info!(
+                    "Teardown: detached {} [{}] ({})",
+                    record.mount_point.display(),
+                    record.fs_type,
+                    record.step
+                );
+                report.unmounted.push(record.mount_point);
+            }
+            Err(e) => {
+                log::warn!(
+                    "Teardown: failed to detach {}: {}",
+                    record.mount_point.display(),
+                    e
+                );
+                report.failed.push(record.mount_point.clone());
+                remaining.push(record);
+            }
+        }
+    }
+
+    remaining.reverse();
+    rewrite(&remaining)?;
+
+    Ok(report)
+}
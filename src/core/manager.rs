@@ -1,7 +1,10 @@
 // Copyright 2026 Hybrid Mount Developers
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::path::Path;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 
 use anyhow::Result;
 
@@ -10,14 +13,27 @@ use crate::{
     core::{
         inventory,
         inventory::model as modules,
-        ops::{backup as granary, executor, planner, sync},
+        journal,
+        ops::{
+            backup as granary, executor, planner, sync,
+            transaction::{MountTransaction, StepFailure},
+        },
         state, storage,
         storage::{StorageHandle, get_usage},
     },
+    defs,
 };
 
 pub struct Init;
 
+// Where the storage steps mount: the storage root itself and the EROFS staging tmpfs.
+fn storage_roots(mnt_base: &Path) -> Vec<PathBuf> {
+    vec![
+        mnt_base.to_path_buf(),
+        PathBuf::from(defs::EROFS_STAGING_DIR),
+    ]
+}
+
 pub struct StorageReady {
     pub handle: StorageHandle,
 }
@@ -25,11 +41,13 @@ pub struct StorageReady {
 pub struct ModulesReady {
     pub handle: StorageHandle,
     pub modules: Vec<inventory::Module>,
+    pub stats: BTreeMap<String, state::ModuleStats>,
 }
 
 pub struct Planned {
     pub handle: StorageHandle,
     pub modules: Vec<inventory::Module>,
+    pub stats: BTreeMap<String, state::ModuleStats>,
     pub plan: planner::MountPlan,
 }
 
@@ -37,6 +55,7 @@ pub struct Executed {
     pub handle: StorageHandle,
     #[allow(dead_code)]
     pub modules: Vec<inventory::Module>,
+    pub stats: BTreeMap<String, state::ModuleStats>,
     pub plan: planner::MountPlan,
     pub result: executor::ExecutionResult,
 }
@@ -59,20 +78,31 @@ impl MountController<Init> {
         mnt_base: &Path,
         img_path: &Path,
     ) -> Result<MountController<StorageReady>> {
-        let handle = storage::setup(
-            mnt_base,
-            img_path,
-            &self.config.moduledir,
-            matches!(
-                self.config.overlay_mode,
-                crate::conf::config::OverlayMode::Ext4
-            ),
-            matches!(
-                self.config.overlay_mode,
-                crate::conf::config::OverlayMode::Erofs
-            ),
-            &self.config.mountsource,
-            self.config.disable_umount,
+        if let Err(e) = journal::reset() {
+            log::warn!("Failed to reset mount journal: {:#}", e);
+        }
+
+        let mut tx = MountTransaction::begin(storage_roots(mnt_base))?;
+        let handle = tx.step(
+            "storage",
+            |_| Vec::new(),
+            || {
+                storage::setup(
+                    mnt_base,
+                    img_path,
+                    &self.config.moduledir,
+                    matches!(
+                        self.config.overlay_mode,
+                        crate::conf::config::OverlayMode::Ext4
+                    ),
+                    matches!(
+                        self.config.overlay_mode,
+                        crate::conf::config::OverlayMode::Erofs
+                    ),
+                    &self.config.mountsource,
+                    self.config.disable_umount,
+                )
+            },
         )?;
 
         log::info!(">> Storage Backend: [{}]", handle.mode.to_uppercase());
@@ -93,15 +123,12 @@ impl MountController<StorageReady> {
             modules.len()
         );
 
-        sync::perform_sync(&modules, &self.state.handle.mount_point)?;
+        let stats = sync::perform_sync(&modules, &self.state.handle.mount_point, &self.config)?;
 
         if self.state.handle.mode == "erofs_staging" {
+            // Any path rule can split a directory and hand the loose files to Magic Mount.
             let needs_magic = modules.iter().any(|m| {
-                m.rules.default_mode == inventory::MountMode::Magic
-                    || m.rules
-                        .paths
-                        .values()
-                        .any(|v| *v == inventory::MountMode::Magic)
+                m.rules.default_mode == inventory::MountMode::Magic || !m.rules.paths.is_empty()
             });
 
             if needs_magic {
@@ -112,13 +139,20 @@ impl MountController<StorageReady> {
             }
         }
 
-        self.state.handle.commit(self.config.disable_umount)?;
+        let disable_umount = self.config.disable_umount;
+        let handle = &mut self.state.handle;
+        MountTransaction::begin(storage_roots(Path::new(&self.config.hybrid_mnt_dir)))?.step(
+            "storage commit",
+            |_| Vec::new(),
+            || handle.commit(disable_umount),
+        )?;
 
         Ok(MountController {
             config: self.config,
             state: ModulesReady {
                 handle: self.state.handle,
                 modules,
+                stats,
             },
         })
     }
@@ -137,6 +171,7 @@ impl MountController<ModulesReady> {
             state: Planned {
                 handle: self.state.handle,
                 modules: self.state.modules,
+                stats: self.state.stats,
                 plan,
             },
         })
@@ -144,16 +179,52 @@ impl MountController<ModulesReady> {
 }
 
 impl MountController<Planned> {
+    fn record_failure(&self, error: &anyhow::Error) {
+        let mut state = state::RuntimeState::new(
+            self.state.handle.mode.clone(),
+            self.state.handle.mount_point.clone(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            get_usage(&self.state.handle.mount_point),
+            Vec::new(),
+        );
+        state.module_stats = self.state.stats.clone();
+
+        match error.downcast_ref::<StepFailure>() {
+            Some(failure) => {
+                state.failed_step = Some(failure.step.clone());
+                state.failure_reason = Some(failure.reason.clone());
+                state.rolled_back = failure.rolled_back;
+            }
+            None => {
+                state.failed_step = Some("prepare".to_string());
+                state.failure_reason = Some(format!("{:#}", error));
+            }
+        }
+
+        if let Err(e) = state.save() {
+            log::error!("Failed to save runtime state: {:#}", e);
+        }
+    }
+
     pub fn execute(self) -> Result<MountController<Executed>> {
         log::info!(">> Link Start! Executing mount plan...");
 
-        let result = executor::execute(&self.state.plan, &self.config)?;
+        let result = match executor::execute(&self.state.plan, &self.config) {
+            Ok(result) => result,
+            Err(e) => {
+                self.record_failure(&e);
+                return Err(e);
+            }
+        };
 
         Ok(MountController {
             config: self.config,
             state: Executed {
                 handle: self.state.handle,
                 modules: self.state.modules,
+                stats: self.state.stats,
                 plan: self.state.plan,
                 result,
             },
@@ -182,14 +253,16 @@ impl MountController<Executed> {
         active_mounts.sort();
         active_mounts.dedup();
 
-        let state = state::RuntimeState::new(
+        let mut state = state::RuntimeState::new(
             self.state.handle.mode,
             self.state.handle.mount_point,
             self.state.result.overlay_module_ids,
             self.state.result.magic_module_ids,
             active_mounts,
             storage_stats,
+            self.state.result.degraded_mounts,
         );
+        state.module_stats = self.state.stats;
 
         if let Err(e) = state.save() {
             log::error!("Failed to save runtime state: {:#}", e);
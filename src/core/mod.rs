@@ -1,7 +1,10 @@
 // Copyright 2026 Hybrid Mount Developers
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+pub mod error;
+pub mod events;
 pub mod inventory;
+pub mod journal;
 pub mod manager;
 pub mod ops;
 pub mod state;
@@ -11,7 +11,14 @@ use std::{
 use anyhow::{Context, Result, bail};
 use serde::{Deserialize, Serialize};
 
-use crate::{conf::config::Config, defs, utils};
+use crate::{
+    conf::config::Config,
+    core::{
+        error::{ErrorKind, TypedError},
+        state::RuntimeState,
+    },
+    defs, utils,
+};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Snapshot {
@@ -62,6 +69,17 @@ pub fn ensure_recovery_state() -> Result<RecoveryStatus> {
 
     log::info!(">> Recovery Protocol: Boot counter at {}", count);
 
+    if let Ok(state) = RuntimeState::load()
+        && let Some(step) = &state.failed_step
+    {
+        log::warn!(
+            ">> Previous boot failed at step '{}' (rolled back: {}): {}",
+            step,
+            state.rolled_back,
+            state.failure_reason.as_deref().unwrap_or("unknown")
+        );
+    }
+
     if count >= 3 {
         log::error!(">> RECOVERY TRIGGERED: Detected potential bootloop (3 failed boots).");
         log::warn!(">> Executing emergency rollback from Backups...");
@@ -170,7 +188,10 @@ pub fn delete_snapshot(id: &str) -> Result<()> {
         log::info!("Deleted Snapshot: {}", id);
         Ok(())
     } else {
-        bail!("Snapshot {} not found", id);
+        bail!(TypedError::new(
+            ErrorKind::SnapshotMissing,
+            format!("Snapshot {} not found", id)
+        ));
     }
 }
 
@@ -178,7 +199,10 @@ pub fn restore_snapshot(id: &str) -> Result<()> {
     let file_path = Path::new(defs::BACKUPS_DIR).join(format!("{}.json", id));
 
     if !file_path.exists() {
-        bail!("Snapshot {} not found", id);
+        bail!(TypedError::new(
+            ErrorKind::SnapshotMissing,
+            format!("Snapshot {} not found", id)
+        ));
     }
 
     let content = fs::read_to_string(&file_path)?;
@@ -2,36 +2,171 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
 };
 
 use anyhow::Result;
 
 use crate::{
-    conf::config,
-    core::ops::planner::MountPlan,
+    conf::config::{self, RollbackPolicy},
+    core::{
+        events::{self, Event},
+        ops::{
+            planner::MountPlan,
+            transaction::{MountTransaction, StepFailure},
+        },
+        state::{DegradeStrategy, DegradedMount},
+    },
     defs,
     mount::{magic_mount, overlayfs, umount_mgr},
     utils,
 };
 
+// Chunked stacking keeps every layer visible, but a whiteout or opaque directory in one chunk
+// cannot hide files from the chunks below it.
+const STACKED_REASON: &str = "layer count exceeds the lowerdir limit; layers were stacked in \
+                              chunks, so whiteouts and opaque directories do not reach layers \
+                              in lower chunks";
+
 pub struct ExecutionResult {
     pub overlay_module_ids: Vec<String>,
     pub magic_module_ids: Vec<String>,
+    pub degraded_mounts: Vec<DegradedMount>,
+}
+
+pub enum ExecutionScope {
+    Full,
+    Partial {
+        targets: HashSet<String>,
+        magic: bool,
+    },
+}
+
+impl ExecutionScope {
+    fn includes(&self, target: &str) -> bool {
+        match self {
+            Self::Full => true,
+            Self::Partial { targets, .. } => targets.contains(target),
+        }
+    }
+
+    fn runs_magic(&self) -> bool {
+        match self {
+            Self::Full => true,
+            Self::Partial { magic, .. } => *magic,
+        }
+    }
 }
 
 pub fn execute(plan: &MountPlan, config: &config::Config) -> Result<ExecutionResult> {
-    let mut final_magic_ids: HashSet<String> = plan.magic_module_ids.iter().cloned().collect();
+    execute_scoped(plan, config, &ExecutionScope::Full)
+}
+
+pub fn execute_scoped(
+    plan: &MountPlan,
+    config: &config::Config,
+    scope: &ExecutionScope,
+) -> Result<ExecutionResult> {
+    let mut roots: Vec<PathBuf> = plan
+        .overlay_ops
+        .iter()
+        .filter(|op| scope.includes(&op.target))
+        .map(|op| PathBuf::from(&op.target))
+        .collect();
+    roots.push(PathBuf::from(&config.hybrid_mnt_dir));
+    roots.push(PathBuf::from(defs::OVERLAY_STACK_DIR));
+
+    let mut tx = MountTransaction::begin(roots)?;
+    let outcome = run(plan, config, scope, &mut tx);
+
+    let (step, reason) = match &outcome {
+        Ok(result) => match result.degraded_mounts.first() {
+            Some(degraded) if config.rollback_policy == RollbackPolicy::Degraded => (
+                format!("overlay {}", degraded.target),
+                format!("degraded mount: {}", degraded.reason),
+            ),
+            _ => {
+                #[cfg(any(target_os = "linux", target_os = "android"))]
+                if !config.disable_umount
+                    && let Err(e) = umount_mgr::commit()
+                {
+                    log::warn!("Final try_umount commit failed: {}", e);
+                }
+
+                events::emit(Event::MountResult {
+                    overlay_modules: result.overlay_module_ids.clone(),
+                    magic_modules: result.magic_module_ids.clone(),
+                    degraded_mounts: result.degraded_mounts.clone(),
+                    failed_step: None,
+                    reason: None,
+                });
+
+                return outcome;
+            }
+        },
+        Err(e) => (tx.current_step().to_string(), format!("{:#}", e)),
+    };
+
+    log::error!("!! Mount step '{}' failed: {}", step, reason);
+
+    let rolled_back = match config.rollback_policy {
+        RollbackPolicy::Never => false,
+        _ => tx.rollback() == 0,
+    };
+
+    events::emit(Event::MountResult {
+        overlay_modules: Vec::new(),
+        magic_modules: Vec::new(),
+        degraded_mounts: Vec::new(),
+        failed_step: Some(step.clone()),
+        reason: Some(reason.clone()),
+    });
+
+    Err(StepFailure {
+        step,
+        rolled_back,
+        reason,
+    }
+    .into())
+}
+
+fn run(
+    plan: &MountPlan,
+    config: &config::Config,
+    scope: &ExecutionScope,
+    tx: &mut MountTransaction,
+) -> Result<ExecutionResult> {
+    // A partial run still hands overlay failures to Magic Mount, but only re-runs the
+    // planned magic modules when asked to.
+    let mut final_magic_ids: HashSet<String> = if scope.runs_magic() {
+        plan.magic_module_ids.iter().cloned().collect()
+    } else {
+        HashSet::new()
+    };
     let mut final_overlay_ids: HashSet<String> = HashSet::new();
+    let mut magic_scopes: HashMap<String, Vec<PathBuf>> = plan.magic_paths.clone();
+    let mut degraded_mounts: Vec<DegradedMount> = Vec::new();
+    let storage_root = Path::new(&config.hybrid_mnt_dir);
+
+    let magic_scope = |id: &str, layer: &Path| {
+        layer
+            .strip_prefix(storage_root.join(id))
+            .map(Path::to_path_buf)
+            .unwrap_or_default()
+    };
 
     log::info!(">> Phase 1: OverlayFS Execution...");
 
-    for op in &plan.overlay_ops {
-        let involved_modules: Vec<String> = op
+    for op in plan
+        .overlay_ops
+        .iter()
+        .filter(|op| scope.includes(&op.target))
+    {
+        let involved_modules: Vec<(String, &PathBuf)> = op
             .lowerdirs
             .iter()
-            .filter_map(|p| utils::extract_module_id(p))
+            .filter_map(|p| utils::extract_module_id(p).map(|id| (id, p)))
             .collect();
 
         let lowerdir_strings: Vec<String> = op
@@ -57,16 +192,65 @@ pub fn execute(plan: &MountPlan, config: &config::Config) -> Result<ExecutionRes
             lowerdir_strings.len()
         );
 
-        match overlayfs::overlayfs::mount_overlay(
-            &op.target,
-            &lowerdir_strings,
-            work_opt,
-            upper_opt,
-            &config.mountsource,
+        let owners: Vec<String> = involved_modules.iter().map(|(id, _)| id.clone()).collect();
+
+        match tx.step(
+            &format!("overlay {}", op.target),
+            |mount_point| {
+                if mount_point.starts_with(&op.target) {
+                    owners.clone()
+                } else {
+                    Vec::new()
+                }
+            },
+            || {
+                overlayfs::overlayfs::mount_overlay(
+                    &op.target,
+                    &lowerdir_strings,
+                    work_opt,
+                    upper_opt,
+                    &config.mountsource,
+                )
+            },
         ) {
-            Ok(_) => {
-                for id in involved_modules {
-                    final_overlay_ids.insert(id);
+            Ok(report) => {
+                let overflow: Vec<PathBuf> = report.overflow.iter().map(PathBuf::from).collect();
+
+                if report.stacked {
+                    degraded_mounts.push(DegradedMount {
+                        target: op.target.clone(),
+                        strategy: DegradeStrategy::Stacked,
+                        modules: involved_modules.iter().map(|(id, _)| id.clone()).collect(),
+                        reason: STACKED_REASON.to_string(),
+                    });
+                }
+
+                let mut overflow_ids = Vec::new();
+                for (id, layer) in involved_modules {
+                    if overflow.contains(layer) {
+                        magic_scopes
+                            .entry(id.clone())
+                            .or_default()
+                            .push(magic_scope(&id, layer));
+                        final_magic_ids.insert(id.clone());
+                        overflow_ids.push(id);
+                    } else {
+                        final_overlay_ids.insert(id);
+                    }
+                }
+
+                if !overflow_ids.is_empty() {
+                    log::warn!(
+                        "{} layers on {} did not fit the overlay and fall back to Magic Mount.",
+                        overflow_ids.len(),
+                        op.target
+                    );
+                    degraded_mounts.push(DegradedMount {
+                        target: op.target.clone(),
+                        strategy: DegradeStrategy::MagicFallback,
+                        modules: overflow_ids,
+                        reason: "layers overflowed the overlay".to_string(),
+                    });
                 }
 
                 #[cfg(any(target_os = "linux", target_os = "android"))]
@@ -82,7 +266,17 @@ pub fn execute(plan: &MountPlan, config: &config::Config) -> Result<ExecutionRes
                     op.target,
                     e
                 );
-                for id in involved_modules {
+                degraded_mounts.push(DegradedMount {
+                    target: op.target.clone(),
+                    strategy: DegradeStrategy::MagicFallback,
+                    modules: involved_modules.iter().map(|(id, _)| id.clone()).collect(),
+                    reason: format!("{:#}", e),
+                });
+                for (id, layer) in involved_modules {
+                    magic_scopes
+                        .entry(id.clone())
+                        .or_default()
+                        .push(magic_scope(&id, layer));
                     final_magic_ids.insert(id);
                 }
             }
@@ -92,7 +286,7 @@ pub fn execute(plan: &MountPlan, config: &config::Config) -> Result<ExecutionRes
     final_overlay_ids.retain(|id| !final_magic_ids.contains(id));
 
     let mut magic_queue: Vec<String> = final_magic_ids.iter().cloned().collect();
-    magic_queue.sort();
+    magic_queue.sort_by(|a, b| plan.rank(a).cmp(&plan.rank(b)).then_with(|| b.cmp(a)));
 
     if !magic_queue.is_empty() {
         let tempdir = PathBuf::from(&config.hybrid_mnt_dir).join("magic_workspace");
@@ -105,7 +299,11 @@ pub fn execute(plan: &MountPlan, config: &config::Config) -> Result<ExecutionRes
 
         if matches!(config.overlay_mode, config::OverlayMode::Erofs) {
             if tempdir.exists() {
-                crate::sys::mount::mount_tmpfs(&tempdir, "magic_ws")?;
+                tx.step(
+                    "magic workspace",
+                    |_| Vec::new(),
+                    || crate::sys::mount::mount_tmpfs(&tempdir, "magic_ws"),
+                )?;
                 #[cfg(any(target_os = "linux", target_os = "android"))]
                 if let Err(e) = umount_mgr::send_umountable(&tempdir) {
                     log::warn!("Failed to schedule unmount for magic_ws: {}", e);
@@ -118,28 +316,46 @@ pub fn execute(plan: &MountPlan, config: &config::Config) -> Result<ExecutionRes
         }
 
         let module_dir = Path::new(&config.hybrid_mnt_dir);
-        let magic_need_ids: HashSet<String> = magic_queue.iter().cloned().collect();
-
-        if let Err(e) = magic_mount::magic_mount(
-            &tempdir,
-            module_dir,
-            &config.mountsource,
-            &config.partitions,
-            magic_need_ids,
-            !config.disable_umount,
-        ) {
+        let magic_need_ids: Vec<(String, Vec<PathBuf>)> = magic_queue
+            .iter()
+            .map(|id| (id.clone(), magic_scopes.remove(id).unwrap_or_default()))
+            .collect();
+
+        // Magic Mount binds onto whichever paths the module trees provide, so those are the
+        // mounts this step may claim.
+        let magic_owner = |mount_point: &Path| -> Vec<String> {
+            let relative = mount_point.strip_prefix("/").unwrap_or(mount_point);
+            let in_system = Path::new("system").join(relative);
+            magic_queue
+                .iter()
+                .find(|id| {
+                    let root = module_dir.join(id);
+                    root.join(relative).symlink_metadata().is_ok()
+                        || root.join(&in_system).symlink_metadata().is_ok()
+                })
+                .cloned()
+                .into_iter()
+                .collect()
+        };
+
+        if let Err(e) = tx.step("magic mount", magic_owner, || {
+            magic_mount::magic_mount(
+                &tempdir,
+                module_dir,
+                &config.mountsource,
+                &config.partitions,
+                magic_need_ids,
+                !config.disable_umount,
+            )
+        }) {
+            if config.rollback_policy != RollbackPolicy::Never {
+                return Err(e.context("Magic Mount critical failure"));
+            }
             log::error!("Magic Mount critical failure: {:#}", e);
             final_magic_ids.clear();
         }
     }
 
-    #[cfg(any(target_os = "linux", target_os = "android"))]
-    if !config.disable_umount
-        && let Err(e) = umount_mgr::commit()
-    {
-        log::warn!("Final try_umount commit failed: {}", e);
-    }
-
     let mut result_overlay: Vec<String> = final_overlay_ids.into_iter().collect();
     let mut result_magic: Vec<String> = final_magic_ids.into_iter().collect();
 
@@ -149,5 +365,6 @@ pub fn execute(plan: &MountPlan, config: &config::Config) -> Result<ExecutionRes
     Ok(ExecutionResult {
         overlay_module_ids: result_overlay,
         magic_module_ids: result_magic,
+        degraded_mounts,
     })
 }
@@ -0,0 +1,339 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Result, bail};
+use serde::Serialize;
+
+use crate::{
+    conf::config::Config,
+    core::{
+        inventory::model::ModuleFile,
+        ops::planner::{DeliveryMode, EntryKind, MountPlan},
+    },
+    mount::{
+        magic_mount::utils::collect_module_files,
+        node::{Node, NodeFileType},
+    },
+    sys::sysroot::SysRoot,
+    utils,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShadowKind {
+    Whiteout,
+    Opaque,
+    Replace,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PathProvider {
+    pub module_id: String,
+    pub mode: DeliveryMode,
+    pub source: PathBuf,
+    pub kind: EntryKind,
+    pub visible: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PathShadow {
+    pub module_id: String,
+    pub mode: DeliveryMode,
+    pub source: PathBuf,
+    pub kind: ShadowKind,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PathExplanation {
+    pub path: PathBuf,
+    pub stock_exists: bool,
+    pub providers: Vec<PathProvider>,
+    pub winner: Option<String>,
+    pub delivered_by: Option<DeliveryMode>,
+    pub stock_hidden: bool,
+    pub hidden_by: Vec<PathShadow>,
+}
+
+fn resolve_path(sysroot: SysRoot, path: &Path) -> Result<PathBuf> {
+    if !path.is_absolute() {
+        bail!("Path must be absolute: {}", path.display());
+    }
+
+    let mut normalized = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            _ => {}
+        }
+    }
+
+    if let (Some(parent), Some(name)) = (normalized.parent(), normalized.file_name())
+        && let Ok(canonical_parent) = sysroot.canonicalize(parent)
+    {
+        return Ok(canonical_parent.join(name));
+    }
+
+    Ok(normalized)
+}
+
+// Ops are mounted in target order, so the deepest target holding the path sits on top and
+// hides every op mounted below it.
+fn explain_overlay(plan: &MountPlan, path: &Path, report: &mut PathExplanation) {
+    let mut covered = false;
+
+    for op in plan.overlay_ops.iter().rev() {
+        let Ok(relative) = path.strip_prefix(&op.target) else {
+            continue;
+        };
+
+        let mut blocked = covered;
+        covered = true;
+
+        for layer in &op.lowerdirs {
+            let module_id = utils::extract_module_id(layer).unwrap_or_else(|| "UNKNOWN".into());
+            let mut layer_blocked = blocked;
+
+            let mut ancestor = PathBuf::new();
+            let mut chain: Vec<PathBuf> = vec![PathBuf::new()];
+            for component in relative.components() {
+                ancestor.push(component);
+                chain.push(ancestor.clone());
+            }
+            chain.pop();
+
+            for prefix in chain {
+                let Ok(file) = ModuleFile::new(layer, &prefix) else {
+                    break;
+                };
+
+                if file.is_whiteout || !file.file_type.is_dir() {
+                    if file.is_whiteout {
+                        report.hidden_by.push(PathShadow {
+                            module_id: module_id.clone(),
+                            mode: DeliveryMode::Overlay,
+                            source: file.real_path,
+                            kind: ShadowKind::Whiteout,
+                        });
+                    }
+                    layer_blocked = true;
+                    blocked = true;
+                    break;
+                }
+
+                if file.is_replace {
+                    report.hidden_by.push(PathShadow {
+                        module_id: module_id.clone(),
+                        mode: DeliveryMode::Overlay,
+                        source: file.real_path,
+                        kind: ShadowKind::Opaque,
+                    });
+                    blocked = true;
+                }
+            }
+
+            let Ok(file) = ModuleFile::new(layer, relative) else {
+                continue;
+            };
+
+            let kind = EntryKind::from(&file);
+
+            if kind == EntryKind::Whiteout {
+                report.hidden_by.push(PathShadow {
+                    module_id: module_id.clone(),
+                    mode: DeliveryMode::Overlay,
+                    source: file.real_path.clone(),
+                    kind: ShadowKind::Whiteout,
+                });
+            } else if file.is_replace {
+                report.hidden_by.push(PathShadow {
+                    module_id: module_id.clone(),
+                    mode: DeliveryMode::Overlay,
+                    source: file.real_path.clone(),
+                    kind: ShadowKind::Opaque,
+                });
+            }
+
+            report.providers.push(PathProvider {
+                module_id,
+                mode: DeliveryMode::Overlay,
+                source: file.real_path,
+                kind,
+                visible: !layer_blocked,
+            });
+
+            if kind != EntryKind::Directory {
+                blocked = true;
+            }
+        }
+    }
+}
+
+fn walk_node<'a>(root: &'a Node, path: &Path) -> (Option<&'a Node>, Vec<&'a Node>) {
+    let mut ancestors = Vec::new();
+    let mut current = root;
+
+    for component in path.components() {
+        let Component::Normal(name) = component else {
+            continue;
+        };
+        ancestors.push(current);
+        match current.children.get(name.to_string_lossy().as_ref()) {
+            Some(child) => current = child,
+            None => return (None, ancestors),
+        }
+    }
+
+    (Some(current), ancestors)
+}
+
+fn node_kind(node: &Node) -> EntryKind {
+    match node.file_type {
+        NodeFileType::RegularFile => EntryKind::File,
+        NodeFileType::Directory => EntryKind::Directory,
+        NodeFileType::Symlink => EntryKind::Symlink,
+        NodeFileType::Whiteout => EntryKind::Whiteout,
+    }
+}
+
+fn magic_shadow(node: &Node) -> Option<PathShadow> {
+    let source = node.module_path.as_ref()?;
+
+    let kind = if node.replace {
+        ShadowKind::Replace
+    } else if node.file_type == NodeFileType::Whiteout {
+        ShadowKind::Whiteout
+    } else {
+        return None;
+    };
+
+    Some(PathShadow {
+        module_id: utils::extract_module_id(source).unwrap_or_else(|| "UNKNOWN".into()),
+        mode: DeliveryMode::Magic,
+        source: source.clone(),
+        kind,
+    })
+}
+
+fn explain_magic(
+    plan: &MountPlan,
+    module_dir: &Path,
+    partitions: &[String],
+    sysroot: SysRoot,
+    path: &Path,
+    report: &mut PathExplanation,
+) -> Result<()> {
+    if plan.magic_module_ids.is_empty() {
+        return Ok(());
+    }
+
+    let ordered = plan.ordered_magic_paths();
+    let Some(combined) = collect_module_files(module_dir, partitions, &ordered, sysroot)? else {
+        return Ok(());
+    };
+
+    let (winner_node, ancestors) = walk_node(&combined, path);
+
+    report.hidden_by.extend(
+        ancestors
+            .into_iter()
+            .chain(winner_node)
+            .filter_map(magic_shadow),
+    );
+
+    let winner_source = winner_node.and_then(|node| node.module_path.clone());
+
+    for (module_id, scope) in ordered.iter() {
+        let need_ids = [(module_id.clone(), scope.clone())];
+        let Some(root) = collect_module_files(module_dir, partitions, &need_ids, sysroot)? else {
+            continue;
+        };
+
+        let (Some(node), _) = walk_node(&root, path) else {
+            continue;
+        };
+        let Some(source) = node.module_path.clone() else {
+            continue;
+        };
+
+        let visible =
+            winner_source.as_ref() == Some(&source) || node_kind(node) == EntryKind::Directory;
+
+        report.providers.push(PathProvider {
+            module_id: module_id.clone(),
+            mode: DeliveryMode::Magic,
+            source,
+            kind: node_kind(node),
+            visible,
+        });
+    }
+
+    if let Some(source) = winner_source {
+        report.providers.sort_by_key(|p| p.source != source);
+    }
+
+    Ok(())
+}
+
+pub fn explain(
+    plan: &MountPlan,
+    config: &Config,
+    module_dir: &Path,
+    path: &Path,
+) -> Result<PathExplanation> {
+    let sysroot = SysRoot::new(&config.sysroot);
+    let path = resolve_path(sysroot, path)?;
+
+    let mut report = PathExplanation {
+        stock_exists: sysroot.host_path(&path).symlink_metadata().is_ok(),
+        path,
+        providers: Vec::new(),
+        winner: None,
+        delivered_by: None,
+        stock_hidden: false,
+        hidden_by: Vec::new(),
+    };
+
+    let path = report.path.clone();
+
+    explain_overlay(plan, &path, &mut report);
+    explain_magic(
+        plan,
+        module_dir,
+        &config.partitions,
+        sysroot,
+        &path,
+        &mut report,
+    )?;
+
+    // Magic Mount binds on top of the finished overlays, so its winner takes precedence.
+    let winner = report
+        .providers
+        .iter()
+        .filter(|p| p.visible && p.kind != EntryKind::Whiteout)
+        .find(|p| p.mode == DeliveryMode::Magic)
+        .or_else(|| {
+            report
+                .providers
+                .iter()
+                .find(|p| p.visible && p.kind != EntryKind::Whiteout)
+        });
+
+    if let Some(winner) = winner {
+        report.winner = Some(winner.module_id.clone());
+        report.delivered_by = Some(winner.mode);
+    }
+
+    report.stock_hidden = !report.hidden_by.is_empty()
+        || (report.stock_exists
+            && report
+                .providers
+                .iter()
+                .any(|p| p.visible && p.kind != EntryKind::Directory));
+
+    Ok(report)
+}
@@ -0,0 +1,296 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    os::unix::fs::{FileTypeExt, MetadataExt},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use crate::{defs, utils};
+
+const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryType {
+    File,
+    Dir,
+    Symlink,
+    Device,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    #[serde(rename = "type")]
+    pub entry_type: EntryType,
+    pub size: u64,
+    pub mtime: i64,
+    pub mtime_nsec: i64,
+    pub mode: u32,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub xattrs: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+impl ManifestEntry {
+    // Whether the two entries can differ only in metadata. Without hashes a different mtime
+    // has to be taken as new content.
+    pub fn same_content(&self, other: &Self) -> bool {
+        self.entry_type == other.entry_type
+            && self.size == other.size
+            && self.target == other.target
+            && match (&self.hash, &other.hash) {
+                (Some(a), Some(b)) => a == b,
+                _ => self.mtime == other.mtime && self.mtime_nsec == other.mtime_nsec,
+            }
+    }
+}
+
+// A snapshot of a module's source tree, keyed by path relative to the module root.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub entries: BTreeMap<PathBuf, ManifestEntry>,
+}
+
+#[derive(Debug, Default)]
+pub struct ManifestDiff {
+    pub added: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+    pub changed: Vec<PathBuf>,
+}
+
+impl ManifestDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl fmt::Display for ManifestDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} removed, {} changed",
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len()
+        )?;
+        if let Some(first) = self
+            .changed
+            .first()
+            .or(self.added.first())
+            .or(self.removed.first())
+        {
+            write!(f, ", first: {}", first.display())?;
+        }
+        Ok(())
+    }
+}
+
+fn read_xattrs(path: &Path) -> BTreeMap<String, String> {
+    let mut xattrs = BTreeMap::new();
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if let Ok(names) = extattr::llistxattr(path) {
+        for name in names {
+            if let Ok(value) = extattr::lgetxattr(path, &name) {
+                let hex: String = value.iter().map(|b| format!("{:02x}", b)).collect();
+                xattrs.insert(name.to_string_lossy().to_string(), hex);
+            }
+        }
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let _ = path;
+
+    xattrs
+}
+
+impl Manifest {
+    // Only entries accepted by `filter` (relative path, is directory) are recorded.
+    pub fn scan<F>(root: &Path, with_hash: bool, filter: F) -> Result<Self>
+    where
+        F: Fn(&Path, bool) -> bool,
+    {
+        let mut entries = BTreeMap::new();
+
+        let walker = WalkDir::new(root)
+            .min_depth(1)
+            .into_iter()
+            .filter_entry(|e| {
+                e.path().strip_prefix(root).map_or(true, |rel| {
+                    rel.as_os_str().is_empty() || filter(rel, e.file_type().is_dir())
+                })
+            });
+        for entry in walker {
+            let entry = entry.with_context(|| format!("Failed to walk {}", root.display()))?;
+            let path = entry.path();
+            let metadata = entry.metadata()?;
+            let file_type = metadata.file_type();
+
+            let entry_type = if file_type.is_dir() {
+                EntryType::Dir
+            } else if file_type.is_symlink() {
+                EntryType::Symlink
+            } else if file_type.is_char_device()
+                || file_type.is_block_device()
+                || file_type.is_fifo()
+            {
+                EntryType::Device
+            } else {
+                EntryType::File
+            };
+
+            let target = match entry_type {
+                EntryType::Symlink => Some(fs::read_link(path)?),
+                _ => None,
+            };
+            let hash = match entry_type {
+                EntryType::File if with_hash => Some(utils::hash_file(path)?),
+                _ => None,
+            };
+
+            entries.insert(
+                path.strip_prefix(root)?.to_path_buf(),
+                ManifestEntry {
+                    entry_type,
+                    // Directory sizes depend on the filesystem, not on what the module ships.
+                    size: if entry_type == EntryType::Dir {
+                        0
+                    } else {
+                        metadata.size()
+                    },
+                    mtime: metadata.mtime(),
+                    mtime_nsec: metadata.mtime_nsec(),
+                    mode: metadata.mode(),
+                    xattrs: read_xattrs(path),
+                    target,
+                    hash,
+                },
+            );
+        }
+
+        Ok(Self {
+            version: MANIFEST_VERSION,
+            entries,
+        })
+    }
+
+    // The manifest of the last successful sync lives next to the synced copy.
+    pub fn load(module_storage: &Path) -> Option<Self> {
+        let content =
+            fs::read_to_string(module_storage.join(defs::SYNC_MANIFEST_FILE_NAME)).ok()?;
+        let manifest: Self = serde_json::from_str(&content).ok()?;
+
+        (manifest.version == MANIFEST_VERSION).then_some(manifest)
+    }
+
+    pub fn save(&self, module_storage: &Path) -> Result<()> {
+        utils::atomic_write(
+            module_storage.join(defs::SYNC_MANIFEST_FILE_NAME),
+            serde_json::to_string(self)?,
+        )
+    }
+
+    // Drops the stored manifest so the copy counts as unverified until a new one is saved.
+    pub fn invalidate(module_storage: &Path) -> Result<()> {
+        match fs::remove_file(module_storage.join(defs::SYNC_MANIFEST_FILE_NAME)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn diff(&self, previous: &Self) -> ManifestDiff {
+        let mut diff = ManifestDiff::default();
+
+        for (path, entry) in &self.entries {
+            match previous.entries.get(path) {
+                None => diff.added.push(path.clone()),
+                Some(old) if old != entry => diff.changed.push(path.clone()),
+                Some(_) => {}
+            }
+        }
+        diff.removed = previous
+            .entries
+            .keys()
+            .filter(|path| !self.entries.contains_key(*path))
+            .cloned()
+            .collect();
+
+        diff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(size: u64, mtime: i64) -> ManifestEntry {
+        ManifestEntry {
+            entry_type: EntryType::File,
+            size,
+            mtime,
+            mtime_nsec: 0,
+            mode: 0o100644,
+            xattrs: BTreeMap::new(),
+            target: None,
+            hash: None,
+        }
+    }
+
+    fn manifest(entries: &[(&str, ManifestEntry)]) -> Manifest {
+        Manifest {
+            version: MANIFEST_VERSION,
+            entries: entries
+                .iter()
+                .map(|(path, entry)| (PathBuf::from(path), entry.clone()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn diff_sorts_paths_into_added_removed_and_changed() {
+        let previous = manifest(&[
+            ("system/kept", entry(1, 1)),
+            ("system/edited", entry(1, 1)),
+            ("system/dropped", entry(1, 1)),
+        ]);
+        let current = manifest(&[
+            ("system/kept", entry(1, 1)),
+            ("system/edited", entry(2, 1)),
+            ("system/new", entry(1, 1)),
+        ]);
+
+        let diff = current.diff(&previous);
+        assert_eq!(diff.added, [PathBuf::from("system/new")]);
+        assert_eq!(diff.removed, [PathBuf::from("system/dropped")]);
+        assert_eq!(diff.changed, [PathBuf::from("system/edited")]);
+        assert!(current.diff(&current).is_empty());
+    }
+
+    #[test]
+    fn metadata_only_changes_keep_the_content() {
+        let old = entry(1, 1);
+        let chmod = ManifestEntry {
+            mode: 0o100600,
+            ..old.clone()
+        };
+        let touched = entry(1, 2);
+        let hashed = |mtime| ManifestEntry {
+            hash: Some("abc".to_string()),
+            ..entry(1, mtime)
+        };
+
+        assert!(chmod.same_content(&old));
+        assert!(!touched.same_content(&old));
+        assert!(hashed(2).same_content(&hashed(1)));
+    }
+}
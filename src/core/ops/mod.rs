@@ -3,5 +3,11 @@
 
 pub mod backup;
 pub mod executor;
+pub mod explain;
+pub mod manifest;
 pub mod planner;
+pub mod relabel;
+pub mod reload;
 pub mod sync;
+pub mod transaction;
+pub mod verify;
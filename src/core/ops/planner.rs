@@ -2,7 +2,9 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use std::{
+    cell::OnceCell,
     collections::{HashMap, HashSet, VecDeque},
+    ffi::OsStr,
     fs,
     path::{Path, PathBuf},
 };
@@ -13,9 +15,11 @@ use serde::Serialize;
 use walkdir::WalkDir;
 
 use crate::{
-    conf::config,
-    core::inventory::{Module, MountMode},
-    defs, utils,
+    conf::config::{self, ModuleRules},
+    core::inventory::{Module, MountMode, compare_precedence, model::ModuleFile},
+    defs,
+    sys::sysroot::SysRoot,
+    utils,
 };
 
 #[derive(Debug, Clone)]
@@ -25,11 +29,77 @@ pub struct OverlayOperation {
     pub lowerdirs: Vec<PathBuf>,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct PlanDecision {
+    pub module_id: String,
+    pub path: String,
+    pub mode: MountMode,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IgnoredPath {
+    pub module_id: String,
+    pub path: PathBuf,
+    pub reason: String,
+}
+
 #[derive(Debug, Default)]
 pub struct MountPlan {
     pub overlay_ops: Vec<OverlayOperation>,
     pub overlay_module_ids: Vec<String>,
     pub magic_module_ids: Vec<String>,
+    pub magic_paths: HashMap<String, Vec<PathBuf>>,
+    pub module_order: Vec<String>,
+    pub decisions: Vec<PlanDecision>,
+    pub ignored: Vec<IgnoredPath>,
+    // System root the plan was resolved against; targets are always device paths.
+    pub sysroot: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    Whiteout,
+}
+
+impl From<&ModuleFile> for EntryKind {
+    fn from(file: &ModuleFile) -> Self {
+        if file.is_whiteout {
+            Self::Whiteout
+        } else if file.file_type.is_dir() {
+            Self::Directory
+        } else if file.file_type.is_symlink() {
+            Self::Symlink
+        } else {
+            Self::File
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryMode {
+    Overlay,
+    Magic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShadowState {
+    Hidden,
+    Merged,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ShadowedModule {
+    pub module: String,
+    pub mode: DeliveryMode,
+    pub kind: EntryKind,
+    pub state: ShadowState,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -37,6 +107,102 @@ pub struct ConflictEntry {
     pub partition: String,
     pub relative_path: String,
     pub contending_modules: Vec<String>,
+    pub winner: String,
+    pub winner_mode: DeliveryMode,
+    pub winner_kind: EntryKind,
+    pub shadowed: Vec<ShadowedModule>,
+}
+
+struct LayerEntry {
+    module_id: String,
+    mode: DeliveryMode,
+    path: PathBuf,
+    kind: EntryKind,
+    opaque: bool,
+}
+
+// A conflict is harmless when every hidden entry would have delivered exactly what the winner
+// already delivers: byte-identical files or symlinks pointing at the same target.
+fn is_duplicate(entries: &[LayerEntry], shadowed: &[ShadowedModule]) -> bool {
+    let Some((winner, losers)) = entries.split_first() else {
+        return false;
+    };
+
+    // The winner is hashed at most once, however many modules it hides.
+    let winner_len = fs::metadata(&winner.path).map(|m| m.len()).ok();
+    let winner_hash = OnceCell::new();
+    let same_content = |other: &Path| {
+        if winner_len.is_none() || fs::metadata(other).map(|m| m.len()).ok() != winner_len {
+            return false;
+        }
+        match (
+            winner_hash.get_or_init(|| utils::hash_file(&winner.path).ok()),
+            utils::hash_file(other),
+        ) {
+            (Some(a), Ok(b)) => *a == b,
+            _ => false,
+        }
+    };
+
+    losers
+        .iter()
+        .zip(shadowed)
+        .filter(|(_, s)| s.state == ShadowState::Hidden)
+        .all(|(loser, _)| match (winner.kind, loser.kind) {
+            (EntryKind::File, EntryKind::File) => same_content(&loser.path),
+            (EntryKind::Symlink, EntryKind::Symlink) => {
+                match (fs::read_link(&winner.path), fs::read_link(&loser.path)) {
+                    (Ok(a), Ok(b)) => a == b,
+                    _ => false,
+                }
+            }
+            _ => false,
+        })
+}
+
+// Entries are ordered top layer first. A lower entry only merges into the visible result when
+// it and every entry above it are plain directories; anything else is hidden outright.
+fn resolve_conflict(
+    partition: &str,
+    relative_path: String,
+    entries: &[LayerEntry],
+) -> Option<ConflictEntry> {
+    let (winner, losers) = entries.split_first()?;
+
+    let shadowed: Vec<ShadowedModule> = losers
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let merged = entry.kind == EntryKind::Directory
+                && entries[..=i]
+                    .iter()
+                    .all(|above| above.kind == EntryKind::Directory && !above.opaque);
+            ShadowedModule {
+                module: entry.module_id.clone(),
+                mode: entry.mode,
+                kind: entry.kind,
+                state: if merged {
+                    ShadowState::Merged
+                } else {
+                    ShadowState::Hidden
+                },
+            }
+        })
+        .collect();
+
+    if shadowed.iter().all(|s| s.state == ShadowState::Merged) {
+        return None;
+    }
+
+    Some(ConflictEntry {
+        partition: partition.to_string(),
+        relative_path,
+        contending_modules: entries.iter().map(|e| e.module_id.clone()).collect(),
+        winner: winner.module_id.clone(),
+        winner_mode: winner.mode,
+        winner_kind: winner.kind,
+        shadowed,
+    })
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -57,22 +223,129 @@ pub struct DiagnosticIssue {
 #[derive(Debug, Default)]
 pub struct AnalysisReport {
     pub conflicts: Vec<ConflictEntry>,
+    pub duplicates: Vec<ConflictEntry>,
     pub diagnostics: Vec<DiagnosticIssue>,
 }
 
-#[allow(clippy::collapsible_if)]
 impl MountPlan {
-    pub fn analyze(&self) -> AnalysisReport {
-        let results: Vec<(Vec<ConflictEntry>, Vec<DiagnosticIssue>)> = self
+    pub fn rank(&self, module_id: &str) -> usize {
+        self.module_order
+            .iter()
+            .position(|id| id == module_id)
+            .unwrap_or(usize::MAX)
+    }
+
+    // Magic Mount scopes in precedence order, highest priority first.
+    pub fn ordered_magic_paths(&self) -> Vec<(String, Vec<PathBuf>)> {
+        let mut scopes: Vec<(String, Vec<PathBuf>)> = self
+            .magic_paths
+            .iter()
+            .map(|(id, paths)| (id.clone(), paths.clone()))
+            .collect();
+        scopes.sort_by(|(a, _), (b, _)| self.rank(a).cmp(&self.rank(b)).then_with(|| b.cmp(a)));
+        scopes
+    }
+}
+
+fn dead_symlink(sysroot: SysRoot, module_id: &str, path: &Path) -> Option<DiagnosticIssue> {
+    let target = fs::read_link(path).ok()?;
+    if !target.is_absolute() || sysroot.exists(&target) {
+        return None;
+    }
+
+    Some(DiagnosticIssue {
+        level: DiagnosticLevel::Warning,
+        context: module_id.to_string(),
+        message: format!(
+            "Dead absolute symlink: {} -> {}",
+            path.display(),
+            target.display()
+        ),
+    })
+}
+
+// Maps a module-relative path such as `system/etc/fonts` onto the directory it lands on, so
+// magic scopes line up with the canonical targets used by overlay operations.
+fn resolve_system_path(sysroot: SysRoot, relative: &Path) -> PathBuf {
+    let path = Path::new("/").join(relative);
+    let mut existing = path.as_path();
+    let mut rest = Vec::new();
+
+    while !sysroot.exists(existing) {
+        let (Some(parent), Some(name)) = (existing.parent(), existing.file_name()) else {
+            return path;
+        };
+        rest.push(name);
+        existing = parent;
+    }
+
+    let mut resolved = sysroot
+        .canonicalize(existing)
+        .unwrap_or_else(|_| existing.to_path_buf());
+    resolved.extend(rest.into_iter().rev());
+    resolved
+}
+
+// Walks one layer and keys every entry by the system path it covers once mounted.
+fn walk_layer(
+    sysroot: SysRoot,
+    module_id: &str,
+    mode: DeliveryMode,
+    layer_root: &Path,
+    system_root: &Path,
+    min_depth: usize,
+) -> (Vec<(PathBuf, LayerEntry)>, Vec<DiagnosticIssue>) {
+    let mut entries = Vec::new();
+    let mut diagnostics = Vec::new();
+
+    for entry in WalkDir::new(layer_root)
+        .min_depth(min_depth)
+        .into_iter()
+        .flatten()
+    {
+        if entry.path_is_symlink()
+            && let Some(issue) = dead_symlink(sysroot, module_id, entry.path())
+        {
+            diagnostics.push(issue);
+        }
+
+        if entry.file_name() == defs::REPLACE_DIR_FILE_NAME {
+            continue;
+        }
+
+        if let Ok(rel) = entry.path().strip_prefix(layer_root)
+            && let Ok(file) = ModuleFile::new(layer_root, rel)
+        {
+            entries.push((
+                system_root.join(rel),
+                LayerEntry {
+                    module_id: module_id.to_string(),
+                    mode,
+                    path: entry.path().to_path_buf(),
+                    kind: EntryKind::from(&file),
+                    opaque: file.is_replace,
+                },
+            ));
+        }
+    }
+
+    (entries, diagnostics)
+}
+
+impl MountPlan {
+    pub fn analyze(&self, module_dir: &Path) -> AnalysisReport {
+        type LayerReport = (Vec<(PathBuf, LayerEntry)>, Vec<DiagnosticIssue>);
+        let sysroot = SysRoot::new(&self.sysroot);
+
+        let overlay_results: Vec<LayerReport> = self
             .overlay_ops
             .par_iter()
             .map(|op| {
-                let mut local_conflicts = Vec::new();
-                let mut local_diagnostics = Vec::new();
-                let mut file_map: HashMap<String, Vec<String>> = HashMap::new();
+                let mut entries = Vec::new();
+                let mut diagnostics = Vec::new();
 
-                if !Path::new(&op.target).exists() {
-                    local_diagnostics.push(DiagnosticIssue {
+                if !sysroot.exists(Path::new(&op.target)) {
+                    diagnostics.push(DiagnosticIssue {
                         level: DiagnosticLevel::Critical,
                         context: op.partition_name.clone(),
                         message: format!("Target mount point does not exist: {}", op.target),
@@ -87,68 +360,229 @@ impl MountPlan {
                     let module_id =
                         utils::extract_module_id(layer_path).unwrap_or_else(|| "UNKNOWN".into());
 
-                    for entry in WalkDir::new(layer_path).min_depth(1).into_iter().flatten() {
-                        if entry.path_is_symlink() {
-                            if let Ok(target) = std::fs::read_link(entry.path()) {
-                                if target.is_absolute() && !target.exists() {
-                                    local_diagnostics.push(DiagnosticIssue {
-                                        level: DiagnosticLevel::Warning,
-                                        context: module_id.clone(),
-                                        message: format!(
-                                            "Dead absolute symlink: {} -> {}",
-                                            entry.path().display(),
-                                            target.display()
-                                        ),
-                                    });
-                                }
-                            }
-                        }
+                    let (layer_entries, layer_diagnostics) = walk_layer(
+                        sysroot,
+                        &module_id,
+                        DeliveryMode::Overlay,
+                        layer_path,
+                        Path::new(&op.target),
+                        1,
+                    );
+                    entries.extend(layer_entries);
+                    diagnostics.extend(layer_diagnostics);
+                }
 
-                        if !entry.file_type().is_file() {
-                            continue;
-                        }
+                (entries, diagnostics)
+            })
+            .collect();
 
-                        if let Ok(rel) = entry.path().strip_prefix(layer_path) {
-                            let rel_str = rel.to_string_lossy().to_string();
-                            file_map.entry(rel_str).or_default().push(module_id.clone());
-                        }
-                    }
-                }
+        let magic_results: Vec<LayerReport> = self
+            .ordered_magic_paths()
+            .par_iter()
+            .map(|(module_id, scopes)| {
+                let mut entries = Vec::new();
+                let mut diagnostics = Vec::new();
 
-                for (rel_path, modules) in file_map {
-                    if modules.len() > 1 {
-                        local_conflicts.push(ConflictEntry {
-                            partition: op.partition_name.clone(),
-                            relative_path: rel_path,
-                            contending_modules: modules,
-                        });
+                for scope in scopes {
+                    let layer_root = module_dir.join(module_id).join(scope);
+                    if !layer_root.exists() {
+                        continue;
                     }
+
+                    let (layer_entries, layer_diagnostics) = walk_layer(
+                        sysroot,
+                        module_id,
+                        DeliveryMode::Magic,
+                        &layer_root,
+                        &resolve_system_path(sysroot, scope),
+                        0,
+                    );
+                    entries.extend(layer_entries);
+                    diagnostics.extend(layer_diagnostics);
                 }
 
-                (local_conflicts, local_diagnostics)
+                (entries, diagnostics)
             })
             .collect();
 
         let mut report = AnalysisReport::default();
-        for (c, d) in results {
-            report.conflicts.extend(c);
-            report.diagnostics.extend(d);
+
+        // The planner never leaves one overlay target nested in another, but should one stack
+        // on its parent, it is mounted later and sits on top. Magic Mount then binds over every
+        // overlay, first module in order winning.
+        let mut overlay_map: HashMap<PathBuf, Vec<LayerEntry>> = HashMap::new();
+        for (entries, diagnostics) in overlay_results {
+            let mut op_map: HashMap<PathBuf, Vec<LayerEntry>> = HashMap::new();
+            for (path, entry) in entries {
+                op_map.entry(path).or_default().push(entry);
+            }
+            for (path, mut layers) in op_map {
+                let below = overlay_map.entry(path).or_default();
+                layers.append(below);
+                *below = layers;
+            }
+            report.diagnostics.extend(diagnostics);
+        }
+
+        let mut file_map: HashMap<PathBuf, Vec<LayerEntry>> = HashMap::new();
+        for (entries, diagnostics) in magic_results {
+            for (path, entry) in entries {
+                file_map.entry(path).or_default().push(entry);
+            }
+            report.diagnostics.extend(diagnostics);
+        }
+        for (path, layers) in overlay_map {
+            file_map.entry(path).or_default().extend(layers);
         }
 
-        report.conflicts.sort_by(|a, b| {
+        for (path, entries) in file_map {
+            if entries.len() < 2 {
+                continue;
+            }
+
+            let mut components = path.iter().skip(1);
+            let partition = components
+                .next()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_else(|| "unknown".to_string());
+            let relative_path = components
+                .collect::<PathBuf>()
+                .to_string_lossy()
+                .to_string();
+
+            if let Some(conflict) = resolve_conflict(&partition, relative_path, &entries) {
+                if is_duplicate(&entries, &conflict.shadowed) {
+                    report.duplicates.push(conflict);
+                } else {
+                    report.conflicts.push(conflict);
+                }
+            }
+        }
+
+        let by_path = |a: &ConflictEntry, b: &ConflictEntry| {
             a.partition
                 .cmp(&b.partition)
                 .then_with(|| a.relative_path.cmp(&b.relative_path))
-        });
+        };
+        report.conflicts.sort_by(by_path);
+        report.duplicates.sort_by(by_path);
 
         report
     }
 }
 
 struct ProcessingItem {
+    module_id: String,
     module_source: PathBuf,
     system_target: PathBuf,
     partition_label: String,
+    relative: String,
+}
+
+fn describe_rule(rules: &ModuleRules, relative_path: &str) -> String {
+    let label = |mode: &MountMode| match mode {
+        MountMode::Overlay => "overlay",
+        MountMode::Magic => "magic",
+        MountMode::Ignore => "ignore",
+    };
+
+    match rules.find_rule(relative_path) {
+        Some((pattern, mode)) => format!("path rule '{}' = {}", pattern, label(mode)),
+        None => format!("module default mode = {}", label(&rules.default_mode)),
+    }
+}
+
+fn mode_bit(mode: &MountMode) -> u8 {
+    match mode {
+        MountMode::Overlay => 1,
+        MountMode::Magic => 2,
+        MountMode::Ignore => 4,
+    }
+}
+
+// The modes resolved anywhere below each directory of a partition, gathered in one walk so
+// divergence is not re-walked at every depth of the plan.
+struct ModesBelow(HashMap<String, u8>);
+
+impl ModesBelow {
+    fn collect(rules: &ModuleRules, source: &Path, partition: &str) -> Self {
+        let mut below: HashMap<String, u8> = HashMap::new();
+
+        for entry in WalkDir::new(source).min_depth(1).into_iter().flatten() {
+            let Ok(sub) = entry.path().strip_prefix(source) else {
+                continue;
+            };
+            let relative = format!("{}/{}", partition, sub.to_string_lossy());
+            let bit = mode_bit(&rules.get_mode(&relative));
+
+            // Ancestors always carry every bit their children do, so the first one that
+            // already has it ends the climb.
+            let mut current = relative.as_str();
+            while let Some((parent, _)) = current.rsplit_once('/') {
+                let modes = below.entry(parent.to_string()).or_default();
+                if *modes & bit != 0 {
+                    break;
+                }
+                *modes |= bit;
+                current = parent;
+            }
+        }
+
+        Self(below)
+    }
+
+    fn diverge(&self, relative: &str, mode: &MountMode) -> bool {
+        self.0
+            .get(relative)
+            .is_some_and(|modes| modes & !mode_bit(mode) != 0)
+    }
+}
+
+// Follows a device-side symlink and canonicalizes the result, so every module that reaches the
+// same directory lands in the same overlay group.
+fn resolve_target(sysroot: SysRoot, system_target: &Path) -> PathBuf {
+    let resolved = match sysroot.read_link(system_target) {
+        Ok(target) if target.is_absolute() => target,
+        Ok(target) => system_target
+            .parent()
+            .unwrap_or(Path::new("/"))
+            .join(target),
+        Err(_) => system_target.to_path_buf(),
+    };
+
+    if sysroot.exists(&resolved) {
+        sysroot.canonicalize(&resolved).unwrap_or(resolved)
+    } else {
+        resolved
+    }
+}
+
+// Files next to a split directory cannot get an overlay of their own, so Magic Mount delivers
+// them instead.
+fn deliver_split_file(
+    plan: &mut MountPlan,
+    magic_ids: &mut HashSet<String>,
+    module: &Module,
+    path: PathBuf,
+    relative: String,
+) {
+    if path.file_name() == Some(OsStr::new(defs::REPLACE_DIR_FILE_NAME)) {
+        return;
+    }
+
+    if matches!(module.rules.get_mode(&relative), MountMode::Ignore) {
+        plan.ignored.push(IgnoredPath {
+            module_id: module.id.clone(),
+            path,
+            reason: describe_rule(&module.rules, &relative),
+        });
+    } else {
+        magic_ids.insert(module.id.clone());
+        plan.magic_paths
+            .entry(module.id.clone())
+            .or_default()
+            .push(PathBuf::from(relative));
+    }
 }
 
 pub fn generate(
@@ -156,16 +590,25 @@ pub fn generate(
     modules: &[Module],
     storage_root: &Path,
 ) -> Result<MountPlan> {
-    let mut plan = MountPlan::default();
+    let mut plan = MountPlan {
+        sysroot: config.sysroot.clone(),
+        ..Default::default()
+    };
+    let sysroot = SysRoot::new(&config.sysroot);
 
-    let mut overlay_groups: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    // Each layer keeps its module-relative path so it can still be split after grouping.
+    let mut overlay_groups: HashMap<PathBuf, Vec<(String, PathBuf, String)>> = HashMap::new();
 
-    let mut overlay_ids = HashSet::new();
     let mut magic_ids = HashSet::new();
 
     let sensitive_partitions: HashSet<&str> = defs::SENSITIVE_PARTITIONS.iter().cloned().collect();
 
-    for module in modules {
+    let mut ordered: Vec<&Module> = modules.iter().collect();
+    ordered.sort_by(|a, b| compare_precedence(a, b));
+    plan.module_order = ordered.iter().map(|m| m.id.clone()).collect();
+    let by_id: HashMap<&str, &Module> = ordered.iter().map(|m| (m.id.as_str(), *m)).collect();
+
+    for module in ordered {
         let mut content_path = storage_root.join(&module.id);
         if !content_path.exists() {
             content_path = module.source_path.clone();
@@ -189,97 +632,212 @@ pub fn generate(
                     continue;
                 }
 
-                let mode = module.rules.get_mode(&dir_name);
-                if matches!(mode, MountMode::Magic) {
-                    magic_ids.insert(module.id.clone());
-                    continue;
-                }
-                if matches!(mode, MountMode::Ignore) {
-                    continue;
-                }
-
-                overlay_ids.insert(module.id.clone());
+                plan.decisions.push(PlanDecision {
+                    module_id: module.id.clone(),
+                    path: dir_name.clone(),
+                    mode: module.rules.get_mode(&dir_name),
+                    reason: describe_rule(&module.rules, &dir_name),
+                });
 
+                let mut modes_below = None;
                 let mut queue = VecDeque::new();
                 queue.push_back(ProcessingItem {
+                    module_id: module.id.clone(),
                     module_source: path.clone(),
                     system_target: PathBuf::from("/").join(&dir_name),
                     partition_label: dir_name.clone(),
+                    relative: dir_name.clone(),
                 });
 
                 while let Some(item) = queue.pop_front() {
                     let ProcessingItem {
+                        module_id,
                         module_source,
                         system_target,
                         partition_label,
+                        relative,
                     } = item;
 
-                    if !system_target.exists() {
-                        continue;
+                    let mode = module.rules.get_mode(&relative);
+                    let nested_rules = module.rules.has_nested_rules(&relative)
+                        && modes_below
+                            .get_or_insert_with(|| {
+                                ModesBelow::collect(&module.rules, &path, &dir_name)
+                            })
+                            .diverge(&relative, &mode);
+
+                    if relative != partition_label && module.rules.find_rule(&relative).is_some() {
+                        plan.decisions.push(PlanDecision {
+                            module_id: module_id.clone(),
+                            path: relative.clone(),
+                            mode: mode.clone(),
+                            reason: describe_rule(&module.rules, &relative),
+                        });
                     }
 
-                    let resolved_target = match fs::read_link(&system_target) {
-                        Ok(target) => {
-                            if target.is_absolute() {
-                                target
-                            } else {
-                                system_target
-                                    .parent()
-                                    .unwrap_or(Path::new("/"))
-                                    .join(target)
+                    if !nested_rules {
+                        match mode {
+                            MountMode::Magic => {
+                                magic_ids.insert(module_id.clone());
+                                plan.magic_paths
+                                    .entry(module_id)
+                                    .or_default()
+                                    .push(PathBuf::from(&relative));
+                                continue;
+                            }
+                            MountMode::Ignore => {
+                                plan.ignored.push(IgnoredPath {
+                                    module_id,
+                                    path: module_source,
+                                    reason: describe_rule(&module.rules, &relative),
+                                });
+                                continue;
                             }
+                            MountMode::Overlay => {}
                         }
-                        Err(_) => system_target.clone(),
-                    };
+                    }
 
-                    let canonical_target = if resolved_target.exists() {
-                        match resolved_target.canonicalize() {
-                            Ok(p) => p,
-                            Err(_) => resolved_target,
-                        }
-                    } else {
-                        resolved_target
-                    };
+                    if !sysroot.exists(&system_target) && !nested_rules {
+                        plan.ignored.push(IgnoredPath {
+                            module_id,
+                            path: module_source,
+                            reason: format!(
+                                "target {} does not exist on this device",
+                                system_target.display()
+                            ),
+                        });
+                        continue;
+                    }
+
+                    let canonical_target = resolve_target(sysroot, &system_target);
 
                     let target_name = canonical_target
                         .file_name()
                         .map(|s| s.to_string_lossy())
                         .unwrap_or_default();
 
-                    let should_split = sensitive_partitions.contains(target_name.as_ref())
+                    let should_split = nested_rules
+                        || sensitive_partitions.contains(target_name.as_ref())
                         || target_name == "system";
 
                     if should_split {
                         if let Ok(sub_entries) = fs::read_dir(&module_source) {
                             for sub_entry in sub_entries.flatten() {
                                 let sub_path = sub_entry.path();
+                                let sub_name = sub_entry.file_name();
+                                let sub_relative =
+                                    format!("{}/{}", relative, sub_name.to_string_lossy());
+
                                 if !sub_path.is_dir() {
+                                    if nested_rules {
+                                        deliver_split_file(
+                                            &mut plan,
+                                            &mut magic_ids,
+                                            module,
+                                            sub_path,
+                                            sub_relative,
+                                        );
+                                    }
                                     continue;
                                 }
-                                let sub_name = sub_entry.file_name();
 
                                 queue.push_back(ProcessingItem {
+                                    module_id: module_id.clone(),
                                     module_source: sub_path,
                                     system_target: canonical_target.join(sub_name),
                                     partition_label: partition_label.clone(),
+                                    relative: sub_relative,
                                 });
                             }
                         }
                     } else {
-                        overlay_groups
-                            .entry(canonical_target)
-                            .or_default()
-                            .push(module_source);
+                        overlay_groups.entry(canonical_target).or_default().push((
+                            module_id,
+                            module_source,
+                            relative,
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    // Overlays mount in target order, so a nested target always stacks over its parent. Once
+    // any module splits a target, every module with a layer there is split the same way, which
+    // keeps each nested overlay holding all of its layers in precedence order.
+    loop {
+        let mut targets: Vec<&PathBuf> = overlay_groups.keys().collect();
+        targets.sort();
+        let Some(parent) = targets
+            .windows(2)
+            .find(|pair| pair[1].starts_with(pair[0]))
+            .map(|pair| pair[0].clone())
+        else {
+            break;
+        };
+
+        for (module_id, source, relative) in overlay_groups.remove(&parent).unwrap_or_default() {
+            let Ok(sub_entries) = fs::read_dir(&source) else {
+                continue;
+            };
+            for sub_entry in sub_entries.flatten() {
+                let sub_path = sub_entry.path();
+                let sub_name = sub_entry.file_name();
+                let sub_relative = format!("{}/{}", relative, sub_name.to_string_lossy());
+
+                if sub_path.is_dir() {
+                    let target = resolve_target(sysroot, &parent.join(&sub_name));
+                    // A symlink back up the tree would be split again forever.
+                    if parent.starts_with(&target) {
+                        plan.ignored.push(IgnoredPath {
+                            module_id: module_id.clone(),
+                            path: sub_path,
+                            reason: format!("target {} contains itself", target.display()),
+                        });
+                        continue;
                     }
+                    overlay_groups.entry(target).or_default().push((
+                        module_id.clone(),
+                        sub_path,
+                        sub_relative,
+                    ));
+                } else {
+                    deliver_split_file(
+                        &mut plan,
+                        &mut magic_ids,
+                        by_id[module_id.as_str()],
+                        sub_path,
+                        sub_relative,
+                    );
                 }
             }
         }
     }
 
-    for (target_path, layers) in overlay_groups {
+    let overlay_ids: HashSet<String> = overlay_groups
+        .values()
+        .flatten()
+        .map(|(module_id, _, _)| module_id.clone())
+        .collect();
+
+    for (target_path, mut layers) in overlay_groups {
+        layers.sort_by_key(|(module_id, _, _)| plan.rank(module_id));
+
         let target_str = target_path.to_string_lossy().to_string();
 
-        if !target_path.is_dir() {
+        if !sysroot.is_dir(&target_path) {
+            let reason = if sysroot.exists(&target_path) {
+                format!("target {} is not a directory", target_str)
+            } else {
+                format!("target {} does not exist on this device", target_str)
+            };
+            for (module_id, layer, _) in layers {
+                plan.ignored.push(IgnoredPath {
+                    module_id,
+                    path: layer,
+                    reason: reason.clone(),
+                });
+            }
             continue;
         }
 
@@ -292,14 +850,30 @@ pub fn generate(
         plan.overlay_ops.push(OverlayOperation {
             partition_name,
             target: target_str,
-            lowerdirs: layers,
+            lowerdirs: layers.into_iter().map(|(_, layer, _)| layer).collect(),
         });
     }
 
+    plan.overlay_ops.sort_by(|a, b| a.target.cmp(&b.target));
+
     plan.overlay_module_ids = overlay_ids.into_iter().collect();
     plan.magic_module_ids = magic_ids.into_iter().collect();
     plan.overlay_module_ids.sort();
     plan.magic_module_ids.sort();
+    for paths in plan.magic_paths.values_mut() {
+        paths.sort();
+        paths.dedup();
+    }
+    plan.decisions.sort_by(|a, b| {
+        a.module_id
+            .cmp(&b.module_id)
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    plan.ignored.sort_by(|a, b| {
+        a.module_id
+            .cmp(&b.module_id)
+            .then_with(|| a.path.cmp(&b.path))
+    });
 
     Ok(plan)
 }
@@ -0,0 +1,178 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::{
+    conf::config::Config,
+    core::{
+        error::{ErrorKind, TypedError},
+        state::RuntimeState,
+    },
+    defs,
+    sys::{
+        file_contexts::{FileContexts, FileKind},
+        sysroot::SysRoot,
+    },
+    utils,
+};
+
+#[derive(Debug, Serialize)]
+pub struct LabelMismatch {
+    pub module: String,
+    pub path: PathBuf,
+    pub target: PathBuf,
+    pub current: Option<String>,
+    pub expected: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RelabelReport {
+    pub dry_run: bool,
+    pub sources: Vec<PathBuf>,
+    pub checked: usize,
+    pub mismatched: usize,
+    pub relabeled: usize,
+    pub failed: usize,
+    pub mismatches: Vec<LabelMismatch>,
+}
+
+fn is_partition(config: &Config, name: &str) -> bool {
+    defs::BUILTIN_PARTITIONS.contains(&name) || config.partitions.iter().any(|p| p == name)
+}
+
+// Checks the labels of one module's partition trees against file_contexts, fixing them unless
+// this is a dry run.
+fn relabel_module(
+    config: &Config,
+    contexts: &FileContexts,
+    module_root: &Path,
+    module: &str,
+    dry_run: bool,
+    report: &mut RelabelReport,
+) {
+    for entry in WalkDir::new(module_root).min_depth(1).into_iter().flatten() {
+        let Ok(relative) = entry.path().strip_prefix(module_root) else {
+            continue;
+        };
+        let in_partition = relative
+            .components()
+            .next()
+            .is_some_and(|c| is_partition(config, &c.as_os_str().to_string_lossy()));
+        if !in_partition {
+            continue;
+        }
+
+        let target = Path::new("/").join(relative);
+        let Some(expected) = contexts.lookup(&target, FileKind::of(&entry.file_type())) else {
+            continue;
+        };
+        report.checked += 1;
+
+        let current = utils::lgetfilecon(entry.path()).ok();
+        if current.as_deref() == Some(expected) {
+            continue;
+        }
+
+        let mut mismatch = LabelMismatch {
+            module: module.to_string(),
+            path: entry.path().to_path_buf(),
+            target,
+            current,
+            expected: expected.to_string(),
+            error: None,
+        };
+
+        if !dry_run {
+            match utils::lsetfilecon(entry.path(), expected) {
+                Ok(()) => report.relabeled += 1,
+                Err(e) => mismatch.error = Some(format!("{:#}", e)),
+            }
+        }
+
+        if mismatch.error.is_some() {
+            report.failed += 1;
+        }
+        report.mismatched += 1;
+        report.mismatches.push(mismatch);
+    }
+}
+
+pub fn relabel(config: &Config, module: Option<&str>, dry_run: bool) -> Result<RelabelReport> {
+    let state = RuntimeState::load().context("Failed to load runtime state")?;
+
+    if state.mount_point.as_os_str().is_empty() {
+        bail!("Nothing is mounted yet; boot the daemon before relabeling storage");
+    }
+    if !dry_run && state.storage_mode == "erofs" {
+        bail!("EROFS storage is read-only; use --dry-run to check labels");
+    }
+
+    let contexts = FileContexts::load(SysRoot::new(&config.sysroot).root())?;
+    let storage_root = state.mount_point;
+
+    let modules: Vec<String> = match module {
+        Some(id) => {
+            utils::validate_module_id(id)?;
+            if !storage_root.join(id).is_dir() {
+                bail!(TypedError::new(
+                    ErrorKind::ModuleNotFound,
+                    format!("Module {} has no synced copy in storage", id)
+                ));
+            }
+            vec![id.to_string()]
+        }
+        None => {
+            let mut ids: Vec<String> = fs::read_dir(&storage_root)
+                .with_context(|| format!("Failed to read {}", storage_root.display()))?
+                .flatten()
+                .filter(|e| e.path().is_dir())
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .filter(|name| name != "magic_workspace" && utils::validate_module_id(name).is_ok())
+                .collect();
+            ids.sort();
+            ids
+        }
+    };
+
+    let mut report = RelabelReport {
+        dry_run,
+        sources: contexts.sources.clone(),
+        checked: 0,
+        mismatched: 0,
+        relabeled: 0,
+        failed: 0,
+        mismatches: Vec::new(),
+    };
+
+    for id in &modules {
+        relabel_module(
+            config,
+            &contexts,
+            &storage_root.join(id),
+            id,
+            dry_run,
+            &mut report,
+        );
+    }
+
+    log::info!(
+        "Relabel{}: {} checked, {} mismatched, {} relabeled, {} failed",
+        if dry_run { " (dry run)" } else { "" },
+        report.checked,
+        report.mismatched,
+        report.relabeled,
+        report.failed
+    );
+
+    Ok(report)
+}
@@ -0,0 +1,330 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{collections::HashSet, fs, path::Path};
+
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+
+use crate::{
+    conf::config::Config,
+    core::{
+        error::{ErrorKind, TypedError},
+        events::SyncOutcome,
+        inventory,
+        journal::{self, MountRecord},
+        ops::{
+            executor::{self, ExecutionScope},
+            planner::{self, MountPlan},
+            sync,
+            transaction::StepFailure,
+        },
+        state::{DegradeStrategy, RuntimeState},
+    },
+    utils,
+};
+
+const MAGIC_STEP: &str = "magic mount";
+
+#[derive(Debug, Serialize)]
+pub struct ReloadReport {
+    pub module: String,
+    pub enabled: bool,
+    pub targets: Vec<String>,
+    pub magic_rerun: bool,
+    pub detached: usize,
+    pub overlay_modules: Vec<String>,
+    pub magic_modules: Vec<String>,
+}
+
+fn overlay_target(record: &MountRecord) -> Option<&str> {
+    record.step.strip_prefix("overlay ")
+}
+
+fn planned_targets(plan: &MountPlan, module_id: &str) -> HashSet<String> {
+    plan.overlay_ops
+        .iter()
+        .filter(|op| {
+            op.lowerdirs
+                .iter()
+                .any(|layer| utils::extract_module_id(layer).as_deref() == Some(module_id))
+        })
+        .map(|op| op.target.clone())
+        .collect()
+}
+
+fn magic_under(records: &[MountRecord], targets: &HashSet<String>) -> bool {
+    records
+        .iter()
+        .any(|r| r.step == MAGIC_STEP && targets.iter().any(|t| r.mount_point.starts_with(t)))
+}
+
+// Detaches the overlays on `targets` (and the Magic Mount tree when `magic` is set) that are not
+// already gone, returning how many mounts were removed.
+fn detach(targets: &HashSet<String>, magic: bool) -> Result<usize> {
+    let report = journal::detach(|record| {
+        overlay_target(record).is_some_and(|t| targets.contains(t))
+            || (magic && record.step == MAGIC_STEP)
+    })?;
+
+    if !report.failed.is_empty() || !report.covered.is_empty() {
+        bail!(TypedError::new(
+            ErrorKind::MountFailure,
+            format!(
+                "Failed to detach {} mounts: {:?}, covered by other mounts: {:?}",
+                report.failed.len() + report.covered.len(),
+                report.failed,
+                report.covered
+            )
+        ));
+    }
+
+    Ok(report.unmounted.len())
+}
+
+// Magic Mount is one tree shared by every module, so re-running it must also bring back the
+// overlays that fell back to it at boot.
+fn magic_fallback_targets(state: &RuntimeState) -> HashSet<String> {
+    state
+        .degraded_mounts
+        .iter()
+        .filter(|d| d.strategy == DegradeStrategy::MagicFallback)
+        .map(|d| d.target.clone())
+        .collect()
+}
+
+// A reload that fails after detaching leaves those targets unmounted; the saved state has to
+// stop claiming what the journal no longer holds.
+fn record_detached(
+    state: &mut RuntimeState,
+    targets: &HashSet<String>,
+    magic: bool,
+    error: &anyhow::Error,
+) {
+    let records = match journal::load() {
+        Ok(records) => records,
+        Err(e) => {
+            log::error!("Failed to read mount journal: {:#}", e);
+            return;
+        }
+    };
+    let mounted = |id: &String, magic_step: bool| {
+        records.iter().any(|r| {
+            r.modules.contains(id)
+                && if magic_step {
+                    r.step == MAGIC_STEP
+                } else {
+                    overlay_target(r).is_some()
+                }
+        })
+    };
+
+    state.overlay_modules.retain(|id| mounted(id, false));
+    if magic {
+        state.magic_modules.retain(|id| mounted(id, true));
+    }
+    state
+        .degraded_mounts
+        .retain(|d| !targets.contains(&d.target));
+
+    match error.downcast_ref::<StepFailure>() {
+        Some(failure) => {
+            state.failed_step = Some(failure.step.clone());
+            state.failure_reason = Some(failure.reason.clone());
+            state.rolled_back = failure.rolled_back;
+        }
+        None => {
+            state.failed_step = Some("reload".to_string());
+            state.failure_reason = Some(format!("{:#}", error));
+        }
+    }
+
+    if let Err(e) = state.save() {
+        log::error!("Failed to save runtime state: {:#}", e);
+    }
+}
+
+pub fn reload_module(config: &Config, module_id: &str) -> Result<ReloadReport> {
+    let mut state = RuntimeState::load().context("Failed to load runtime state")?;
+
+    if state.mount_point.as_os_str().is_empty() {
+        bail!("Nothing is mounted yet; boot the daemon before reloading modules");
+    }
+    if config.sysroot != Path::new("/") {
+        bail!(TypedError::new(
+            ErrorKind::ConfigInvalid,
+            format!(
+                "Reload mounts onto the live system and cannot use sysroot {}",
+                config.sysroot.display()
+            )
+        ));
+    }
+    if state.storage_mode == "erofs" {
+        bail!("EROFS storage is read-only; a reboot is required to apply module changes");
+    }
+
+    let storage_root = state.mount_point.clone();
+    let records = journal::load()?;
+
+    let mut affected: HashSet<String> = records
+        .iter()
+        .filter(|r| r.modules.iter().any(|m| m == module_id))
+        .filter_map(|r| overlay_target(r).map(str::to_string))
+        .collect();
+
+    let mut magic_rerun =
+        state.magic_modules.iter().any(|m| m == module_id) || magic_under(&records, &affected);
+
+    let modules = inventory::scan(&config.moduledir, config)?;
+    let module = modules.iter().find(|m| m.id == module_id);
+
+    // Only a module that was mounted before may be missing from the scan; then it is removed.
+    let known = module.is_some()
+        || state.overlay_modules.iter().any(|m| m == module_id)
+        || state.magic_modules.iter().any(|m| m == module_id)
+        || records
+            .iter()
+            .any(|r| r.modules.iter().any(|m| m == module_id))
+        || storage_root.join(module_id).exists();
+    if !known {
+        bail!(TypedError::new(
+            ErrorKind::ModuleNotFound,
+            format!("Module {} is neither enabled nor mounted", module_id)
+        ));
+    }
+
+    log::info!(
+        ">> Reloading module {} ({} mounted targets)",
+        module_id,
+        affected.len()
+    );
+
+    if magic_rerun {
+        affected.extend(magic_fallback_targets(&state));
+    }
+
+    let mut detached = detach(&affected, magic_rerun)?;
+
+    // Everything past the first detach runs with mounts already gone, so a failure still has to
+    // be written to the runtime state.
+    let outcome = (|| {
+        match module {
+            Some(module) => {
+                if sync::resync_module(module, &storage_root, config) == SyncOutcome::Failed {
+                    bail!("Failed to sync module {}", module_id);
+                }
+                state.module_stats.insert(
+                    module_id.to_string(),
+                    sync::collect_stats(config, &storage_root.join(module_id)),
+                );
+            }
+            None => {
+                state.module_stats.remove(module_id);
+                log::info!("Module {} is no longer enabled, removing it", module_id);
+                let stale = storage_root.join(module_id);
+                if stale.exists() {
+                    fs::remove_dir_all(&stale)
+                        .with_context(|| format!("Failed to remove {}", stale.display()))?;
+                }
+            }
+        }
+
+        let plan = planner::generate(config, &modules, &storage_root)?;
+
+        // The new plan may reach targets another module already occupies; those overlays have
+        // to be rebuilt with the reloaded layer included.
+        let mut added: HashSet<String> = planned_targets(&plan, module_id)
+            .difference(&affected)
+            .cloned()
+            .collect();
+        let records = journal::load()?;
+        let needs_magic = !magic_rerun
+            && (plan.magic_module_ids.iter().any(|m| m == module_id)
+                || magic_under(&records, &added));
+        if needs_magic {
+            added.extend(
+                magic_fallback_targets(&state)
+                    .difference(&affected)
+                    .cloned(),
+            );
+        }
+
+        affected.extend(added.iter().cloned());
+        magic_rerun |= needs_magic;
+        if !added.is_empty() || needs_magic {
+            detached += detach(&added, needs_magic)?;
+        }
+
+        let result = executor::execute_scoped(
+            &plan,
+            config,
+            &ExecutionScope::Partial {
+                targets: affected.clone(),
+                magic: magic_rerun,
+            },
+        )?;
+        Ok((plan, result))
+    })();
+
+    let (plan, result) = match outcome {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            record_detached(&mut state, &affected, magic_rerun, &e);
+            return Err(e);
+        }
+    };
+
+    let mut overlay: Vec<String> = state
+        .overlay_modules
+        .iter()
+        .filter(|id| *id != module_id && !result.magic_module_ids.contains(id))
+        .cloned()
+        .chain(result.overlay_module_ids.iter().cloned())
+        .collect();
+    overlay.sort();
+    overlay.dedup();
+
+    let mut magic: Vec<String> = if magic_rerun {
+        result.magic_module_ids.clone()
+    } else {
+        state
+            .magic_modules
+            .iter()
+            .filter(|id| *id != module_id)
+            .cloned()
+            .chain(result.magic_module_ids.iter().cloned())
+            .collect()
+    };
+    magic.sort();
+    magic.dedup();
+
+    let mut active_mounts: Vec<String> = plan
+        .overlay_ops
+        .iter()
+        .map(|op| op.partition_name.clone())
+        .collect();
+    active_mounts.sort();
+    active_mounts.dedup();
+
+    state.overlay_modules = overlay.clone();
+    state.magic_modules = magic.clone();
+    state.active_mounts = active_mounts;
+    state
+        .degraded_mounts
+        .retain(|d| !affected.contains(&d.target));
+    state.degraded_mounts.extend(result.degraded_mounts);
+    state.save().context("Failed to save runtime state")?;
+
+    let mut targets: Vec<String> = affected.into_iter().collect();
+    targets.sort();
+
+    Ok(ReloadReport {
+        module: module_id.to_string(),
+        enabled: module.is_some(),
+        targets,
+        magic_rerun,
+        detached,
+        overlay_modules: overlay,
+        magic_modules: magic,
+    })
+}
@@ -1,84 +1,442 @@
 // Copyright 2026 Hybrid Mount Developers
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::{collections::HashSet, fs, path::Path};
-
-use anyhow::Result;
+use std::{
+    collections::{BTreeMap, HashSet},
+    ffi::OsStr,
+    fs,
+    os::unix::fs::{FileTypeExt, MetadataExt},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use anyhow::{Context, Result};
 use rayon::prelude::*;
 use walkdir::WalkDir;
 
-use crate::{core::inventory::Module, defs, utils};
-
-pub fn perform_sync(modules: &[Module], target_base: &Path) -> Result<()> {
+use crate::{
+    conf::config::{Config, SyncMode, pattern_covers, pattern_reaches_below},
+    core::{
+        events::{self, Event, SyncOutcome},
+        inventory::Module,
+        ops::manifest::{EntryType, Manifest, ManifestDiff},
+        state::{FootprintStats, ModuleStats},
+    },
+    defs,
+    sys::sysroot::SysRoot,
+    utils::{self, MetadataFailure},
+};
+
+pub fn perform_sync(
+    modules: &[Module],
+    target_base: &Path,
+    config: &Config,
+) -> Result<BTreeMap<String, ModuleStats>> {
     log::info!("Starting smart module sync to {}", target_base.display());
 
     prune_orphaned_modules(modules, target_base)?;
 
-    modules.par_iter().for_each(|module| {
-        let dst = target_base.join(&module.id);
-        let dst_backup = target_base.join(format!(".backup_{}", module.id));
+    let done = AtomicUsize::new(0);
+
+    Ok(modules
+        .par_iter()
+        .filter_map(|module| {
+            let outcome = sync_module(module, target_base, config, false);
+            events::emit(Event::SyncProgress {
+                module: module.id.clone(),
+                outcome,
+                done: done.fetch_add(1, Ordering::Relaxed) + 1,
+                total: modules.len(),
+            });
+
+            let dst = target_base.join(&module.id);
+            dst.exists()
+                .then(|| (module.id.clone(), collect_stats(config, &dst)))
+        })
+        .collect())
+}
 
-        let has_content = defs::BUILTIN_PARTITIONS.iter().any(|p| {
-            let part_path = module.source_path.join(p);
+// Tallies what a synced module occupies in storage, split by the partition it targets.
+// Files outside any partition (module.prop, markers) only count toward the total.
+pub fn collect_stats(config: &Config, root: &Path) -> ModuleStats {
+    let mut stats = ModuleStats::default();
 
-            part_path.exists() && has_files_recursive(&part_path)
+    for entry in WalkDir::new(root).min_depth(1).into_iter().flatten() {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+
+        let file_type = metadata.file_type();
+        let mut entry_stats = FootprintStats {
+            bytes: metadata.blocks() * 512,
+            ..Default::default()
+        };
+        if file_type.is_dir() {
+            entry_stats.dirs = 1;
+        } else if file_type.is_symlink() {
+            entry_stats.symlinks = 1;
+        } else if file_type.is_char_device() && metadata.rdev() == 0 {
+            entry_stats.whiteouts = 1;
+        } else {
+            entry_stats.files = 1;
+        }
+
+        stats.total.add(&entry_stats);
+
+        let partition = entry
+            .path()
+            .strip_prefix(root)
+            .ok()
+            .and_then(|rel| rel.components().next())
+            .map(|c| c.as_os_str().to_string_lossy().to_string());
+        if let Some(partition) = partition
+            && is_partition(config, &partition)
+        {
+            stats
+                .partitions
+                .entry(partition)
+                .or_default()
+                .add(&entry_stats);
+        }
+    }
+
+    stats
+}
+
+// Re-syncs one module even when its manifest is unchanged, for hot reloads.
+pub fn resync_module(module: &Module, target_base: &Path, config: &Config) -> SyncOutcome {
+    let outcome = sync_module(module, target_base, config, true);
+    events::emit(Event::SyncProgress {
+        module: module.id.clone(),
+        outcome,
+        done: 1,
+        total: 1,
+    });
+    outcome
+}
+
+enum SyncPlan {
+    Unchanged,
+    Full(String),
+    Delta {
+        diff: ManifestDiff,
+        metadata_only: Vec<PathBuf>,
+    },
+}
+
+// Decides how to bring the stored copy in line with the source. A delta is only planned when
+// both manifests are trustworthy and no `.replace` marker came or went, since opaque
+// directories are derived from those markers rather than copied. Hardlinked files also need a
+// full sync, as a delta copies each name on its own and would split them.
+fn plan_sync(
+    force: bool,
+    src: &Path,
+    dst: &Path,
+    manifest: Option<&Manifest>,
+    mode: SyncMode,
+) -> SyncPlan {
+    if force {
+        return SyncPlan::Full("forced".to_string());
+    }
+    if !dst.exists() {
+        return SyncPlan::Full("new".to_string());
+    }
+    let Some(manifest) = manifest else {
+        return SyncPlan::Full("source manifest unavailable".to_string());
+    };
+    let Some(previous) = Manifest::load(dst) else {
+        return SyncPlan::Full("no previous manifest".to_string());
+    };
+
+    let mut diff = manifest.diff(&previous);
+    if diff.is_empty() {
+        return SyncPlan::Unchanged;
+    }
+
+    let touches_replace = diff
+        .added
+        .iter()
+        .chain(&diff.removed)
+        .any(|p| p.file_name() == Some(OsStr::new(defs::REPLACE_DIR_FILE_NAME)));
+    if mode == SyncMode::Full || touches_replace {
+        return SyncPlan::Full(diff.to_string());
+    }
+
+    let (metadata_only, changed) = diff.changed.drain(..).partition(|path| {
+        match (manifest.entries.get(path), previous.entries.get(path)) {
+            (Some(new), Some(old)) => new.entry_type != EntryType::Dir && new.same_content(old),
+            _ => false,
+        }
+    });
+    diff.changed = changed;
+
+    let hardlinked = diff.added.iter().chain(&diff.changed).find(|path| {
+        src.join(path)
+            .symlink_metadata()
+            .is_ok_and(|m| !m.is_dir() && m.nlink() > 1)
+    });
+    if let Some(path) = hardlinked {
+        return SyncPlan::Full(format!("{} is hardlinked", path.display()));
+    }
+
+    SyncPlan::Delta {
+        diff,
+        metadata_only,
+    }
+}
+
+// Crash safety comes from the manifest: it is dropped before the first change and only saved
+// again once the copy matches the source, so an interrupted delta leads to a full atomic
+// recopy on the next sync.
+fn apply_delta(
+    module: &Module,
+    dst: &Path,
+    sysroot: SysRoot,
+    diff: &ManifestDiff,
+    metadata_only: &[PathBuf],
+) -> Result<Vec<MetadataFailure>> {
+    Manifest::invalidate(dst)?;
+
+    for path in diff.removed.iter().rev() {
+        let target = dst.join(path);
+        match target.symlink_metadata() {
+            Ok(m) if m.is_dir() => fs::remove_dir_all(&target)?,
+            Ok(_) => fs::remove_file(&target)?,
+            Err(_) => {}
+        }
+    }
+
+    let mut failures = Vec::new();
+    let mut updates: Vec<&PathBuf> = diff.added.iter().chain(&diff.changed).collect();
+    updates.sort();
+    for path in &updates {
+        failures.extend(utils::sync_entry(
+            &module.source_path.join(path),
+            &dst.join(path),
+            path,
+            Some(sysroot),
+        )?);
+    }
+
+    for path in metadata_only {
+        let target = dst.join(path);
+        failures.extend(if target.symlink_metadata().is_ok() {
+            utils::sync_entry_metadata(
+                &module.source_path.join(path),
+                &target,
+                path,
+                Some(sysroot),
+            )?
+        } else {
+            utils::sync_entry(&module.source_path.join(path), &target, path, Some(sysroot))?
         });
+    }
 
-        if has_content && should_sync(&module.source_path, &dst) {
-            log::info!("Syncing module: {} (Updated/New)", module.id);
+    utils::prune_empty_dirs(dst)?;
+    apply_overlay_opaque_flags(dst)?;
+
+    // Writing into a directory bumps its mtime, so directories get their metadata again once
+    // everything below them is done, deepest first.
+    for path in updates.iter().rev() {
+        let target = dst.join(path);
+        if target.is_dir() {
+            utils::sync_entry_metadata(
+                &module.source_path.join(path),
+                &target,
+                path,
+                Some(sysroot),
+            )?;
+        }
+    }
 
-            let tmp_dst = target_base.join(format!(".tmp_{}", module.id));
+    Ok(failures)
+}
 
-            if tmp_dst.exists() {
-                let _ = fs::remove_dir_all(&tmp_dst);
-            }
+fn is_partition(config: &Config, name: &str) -> bool {
+    defs::BUILTIN_PARTITIONS.contains(&name) || config.partitions.iter().any(|p| p == name)
+}
 
-            if let Err(e) = utils::sync_dir(&module.source_path, &tmp_dst, true) {
-                log::error!("Failed to sync module {}: {}", module.id, e);
-                let _ = fs::remove_dir_all(&tmp_dst);
-                return;
-            }
+// Storage only needs what can be mounted: the partition trees the planner recognises, the
+// metadata files the mount backends read, and whatever the user includes on top. Excludes
+// win over everything else.
+fn wanted(config: &Config, relative: &Path, is_dir: bool) -> bool {
+    let path = relative.to_string_lossy();
+
+    if config
+        .sync_exclude
+        .iter()
+        .any(|pattern| pattern_covers(pattern, &path))
+    {
+        return false;
+    }
 
-            if let Err(e) = utils::prune_empty_dirs(&tmp_dst) {
-                log::warn!("Failed to prune empty dirs for {}: {}", module.id, e);
-            }
+    let mut components = relative.components();
+    let top = components
+        .next()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .unwrap_or_default();
+    let top_level = components.next().is_none();
+
+    if is_partition(config, &top)
+        || (top_level && !is_dir && defs::SYNC_METADATA_FILES.contains(&top.as_ref()))
+    {
+        return true;
+    }
+
+    config.sync_include.iter().any(|pattern| {
+        pattern_covers(pattern, &path) || (is_dir && pattern_reaches_below(pattern, &path))
+    })
+}
 
-            if let Err(e) = apply_overlay_opaque_flags(&tmp_dst) {
+fn full_sync(module: &Module, target_base: &Path, config: &Config) -> Result<Vec<MetadataFailure>> {
+    let dst = target_base.join(&module.id);
+    let dst_backup = target_base.join(format!(".backup_{}", module.id));
+    let tmp_dst = target_base.join(format!(".tmp_{}", module.id));
+    let sysroot = SysRoot::new(&config.sysroot);
+
+    if tmp_dst.exists() {
+        let _ = fs::remove_dir_all(&tmp_dst);
+    }
+
+    let failures = match utils::sync_dir(
+        &module.source_path,
+        &tmp_dst,
+        Some(sysroot),
+        |rel, is_dir| wanted(config, rel, is_dir),
+    ) {
+        Ok(failures) => failures,
+        Err(e) => {
+            let _ = fs::remove_dir_all(&tmp_dst);
+            return Err(e);
+        }
+    };
+
+    if let Err(e) = utils::prune_empty_dirs(&tmp_dst) {
+        log::warn!("Failed to prune empty dirs for {}: {}", module.id, e);
+    }
+
+    if let Err(e) = apply_overlay_opaque_flags(&tmp_dst) {
+        log::warn!(
+            "Failed to apply overlay opaque xattrs for {}: {}",
+            module.id,
+            e
+        );
+    }
+
+    let mut backup_created = false;
+    if dst.exists() {
+        if let Err(e) = fs::rename(&dst, &dst_backup) {
+            let _ = fs::remove_dir_all(&tmp_dst);
+            return Err(e).context("Failed to backup existing module");
+        }
+        backup_created = true;
+    }
+
+    if let Err(e) = fs::rename(&tmp_dst, &dst) {
+        if backup_created {
+            let _ = fs::rename(&dst_backup, &dst);
+        }
+        let _ = fs::remove_dir_all(&tmp_dst);
+        return Err(e).context("Failed to commit atomic sync");
+    }
+
+    if backup_created && let Err(e) = fs::remove_dir_all(&dst_backup) {
+        log::warn!("Failed to clean up backup for {}: {}", module.id, e);
+    }
+
+    Ok(failures)
+}
+
+fn sync_module(module: &Module, target_base: &Path, config: &Config, force: bool) -> SyncOutcome {
+    let dst = target_base.join(&module.id);
+
+    let has_content = defs::BUILTIN_PARTITIONS
+        .iter()
+        .copied()
+        .chain(config.partitions.iter().map(String::as_str))
+        .any(|p| {
+            let part_path = module.source_path.join(p);
+
+            part_path.exists() && has_files_recursive(&part_path)
+        });
+
+    if !has_content {
+        log::debug!("Skipping module: {} (no content)", module.id);
+        return SyncOutcome::Skipped;
+    }
+
+    let manifest = match Manifest::scan(&module.source_path, config.sync_hash, |rel, is_dir| {
+        wanted(config, rel, is_dir)
+    }) {
+        Ok(manifest) => Some(manifest),
+        Err(e) => {
+            log::warn!("Failed to build manifest for {}: {:#}", module.id, e);
+            None
+        }
+    };
+
+    let synced = match plan_sync(
+        force,
+        &module.source_path,
+        &dst,
+        manifest.as_ref(),
+        config.sync_mode,
+    ) {
+        SyncPlan::Unchanged => {
+            log::debug!("Skipping module: {} (unchanged)", module.id);
+            return SyncOutcome::Unchanged;
+        }
+        SyncPlan::Full(reason) => {
+            log::info!("Syncing module: {} ({})", module.id, reason);
+            full_sync(module, target_base, config)
+        }
+        SyncPlan::Delta {
+            diff,
+            metadata_only,
+        } => {
+            log::info!(
+                "Syncing module: {} (delta: {}, {} metadata only)",
+                module.id,
+                diff,
+                metadata_only.len()
+            );
+            apply_delta(
+                module,
+                &dst,
+                SysRoot::new(&config.sysroot),
+                &diff,
+                &metadata_only,
+            )
+            .or_else(|e| {
                 log::warn!(
-                    "Failed to apply overlay opaque xattrs for {}: {}",
+                    "Delta sync failed for {}, falling back to a full copy: {:#}",
                     module.id,
                     e
                 );
-            }
-
-            let mut backup_created = false;
-            if dst.exists() {
-                if let Err(e) = fs::rename(&dst, &dst_backup) {
-                    log::error!("Failed to backup existing module {}: {}", module.id, e);
-                    let _ = fs::remove_dir_all(&tmp_dst);
-                    return;
-                }
-                backup_created = true;
-            }
-
-            if let Err(e) = fs::rename(&tmp_dst, &dst) {
-                log::error!("Failed to commit atomic sync for {}: {}", module.id, e);
-                if backup_created {
-                    let _ = fs::rename(&dst_backup, &dst);
-                }
-                let _ = fs::remove_dir_all(&tmp_dst);
-                return;
-            }
+                full_sync(module, target_base, config)
+            })
+        }
+    };
 
-            if backup_created && let Err(e) = fs::remove_dir_all(&dst_backup) {
-                log::warn!("Failed to clean up backup for {}: {}", module.id, e);
+    match synced {
+        Ok(failures) => {
+            for failure in failures {
+                log::warn!("Metadata not preserved in {}: {}", module.id, failure);
             }
-        } else {
-            log::debug!("Skipping module: {}", module.id);
         }
-    });
+        Err(e) => {
+            log::error!("Failed to sync module {}: {:#}", module.id, e);
+            return SyncOutcome::Failed;
+        }
+    }
 
-    Ok(())
+    if let Some(manifest) = manifest
+        && let Err(e) = manifest.save(&dst)
+    {
+        log::warn!("Failed to save sync manifest for {}: {:#}", module.id, e);
+    }
+
+    SyncOutcome::Synced
 }
 
 fn apply_overlay_opaque_flags(root: &Path) -> Result<()> {
@@ -130,25 +488,6 @@ fn prune_orphaned_modules(modules: &[Module], target_base: &Path) -> Result<()>
     Ok(())
 }
 
-fn should_sync(src: &Path, dst: &Path) -> bool {
-    if !dst.exists() {
-        return true;
-    }
-
-    let src_prop = src.join("module.prop");
-
-    let dst_prop = dst.join("module.prop");
-
-    if !src_prop.exists() || !dst_prop.exists() {
-        return true;
-    }
-
-    match (fs::read(&src_prop), fs::read(&dst_prop)) {
-        (Ok(s), Ok(d)) => s != d,
-        _ => true,
-    }
-}
-
 fn has_files_recursive(path: &Path) -> bool {
     if let Ok(entries) = fs::read_dir(path) {
         for entry in entries.flatten() {
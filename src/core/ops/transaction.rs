@@ -0,0 +1,201 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    collections::HashSet,
+    env, fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use procfs::process::Process;
+use rustix::mount::{UnmountFlags, unmount};
+
+use crate::core::journal::{self, MountRecord, Presence};
+
+#[derive(Debug)]
+pub struct StepFailure {
+    pub step: String,
+    pub rolled_back: bool,
+    pub reason: String,
+}
+
+impl fmt::Display for StepFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "mount step '{}' failed: {}", self.step, self.reason)?;
+        if self.rolled_back {
+            write!(f, " (rolled back to stock)")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for StepFailure {}
+
+// Test hook: when META_HYBRID_PAUSE_AT names the point, the daemon writes the point's name to
+// the fifo at META_HYBRID_PAUSE_FIFO and waits until something is written back, so a test can
+// change the mount table at a known moment instead of racing the daemon.
+fn pause_at(point: &str) {
+    if env::var_os("META_HYBRID_PAUSE_AT").is_none_or(|p| p != point) {
+        return;
+    }
+    let Some(fifo) = env::var_os("META_HYBRID_PAUSE_FIFO") else {
+        return;
+    };
+
+    log::warn!("Transaction: paused before {}", point);
+    if let Err(e) = fs::write(&fifo, point).and_then(|()| fs::read(&fifo)) {
+        log::warn!("Transaction: pause before {} failed: {}", point, e);
+    }
+}
+
+// Tracks the mounts that appear while a step runs, so a failed boot can be
+// unwound in reverse order without guessing which mounts were ours.
+pub struct MountTransaction {
+    known: HashSet<i32>,
+    roots: Vec<PathBuf>,
+    records: Vec<MountRecord>,
+    current: Option<String>,
+}
+
+impl MountTransaction {
+    // Other processes keep mounting while we run, so a new mount is only claimed when it sits
+    // under one of `roots` or a step's owners attribute it to a module.
+    pub fn begin(roots: Vec<PathBuf>) -> Result<Self> {
+        let known = Process::myself()?
+            .mountinfo()
+            .context("Failed to read mountinfo")?
+            .0
+            .iter()
+            .map(|m| m.mnt_id)
+            .collect();
+
+        Ok(Self {
+            known,
+            roots,
+            records: Vec::new(),
+            current: None,
+        })
+    }
+
+    pub fn step<T>(
+        &mut self,
+        name: &str,
+        owners: impl Fn(&Path) -> Vec<String>,
+        f: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        self.current = Some(name.to_string());
+        pause_at(name);
+        let result = f();
+        self.capture(name, owners);
+        result
+    }
+
+    pub fn current_step(&self) -> &str {
+        self.current.as_deref().unwrap_or("prepare")
+    }
+
+    fn capture(&mut self, step: &str, owners: impl Fn(&Path) -> Vec<String>) {
+        let mounts = match Process::myself().and_then(|p| p.mountinfo()) {
+            Ok(mounts) => mounts,
+            Err(e) => {
+                log::warn!(
+                    "Transaction: failed to read mountinfo after {}: {}",
+                    step,
+                    e
+                );
+                return;
+            }
+        };
+
+        let start = self.records.len();
+        for m in mounts.0 {
+            if !self.known.insert(m.mnt_id) {
+                continue;
+            }
+
+            let modules = owners(&m.mount_point);
+            if modules.is_empty() && !self.roots.iter().any(|r| m.mount_point.starts_with(r)) {
+                log::debug!(
+                    "Transaction: ignoring foreign mount {} [{}] during {}",
+                    m.mount_point.display(),
+                    m.fs_type,
+                    step
+                );
+                continue;
+            }
+
+            self.records.push(MountRecord {
+                step: step.to_string(),
+                mount_id: m.mnt_id,
+                modules,
+                mount_point: m.mount_point,
+                fs_type: m.fs_type,
+            });
+        }
+
+        if let Err(e) = journal::append(&self.records[start..]) {
+            log::warn!("Failed to journal mounts of {}: {:#}", step, e);
+        }
+    }
+
+    // Only claimed records are unwound, and only while their mount is still the topmost one at
+    // the recorded mount point; a path whose mount is gone or covered may now hold someone
+    // else's on top.
+    pub fn rollback(&mut self) -> usize {
+        pause_at("rollback");
+        log::warn!(
+            ">> Rolling back {} mounts in reverse order...",
+            self.records.len()
+        );
+
+        let mut failed = 0;
+        let mut detached = HashSet::new();
+        for record in self.records.drain(..).rev() {
+            match record.presence() {
+                Ok(Presence::Gone) => {
+                    log::debug!(
+                        "Rollback: {} ({}) is already gone",
+                        record.mount_point.display(),
+                        record.step
+                    );
+                    detached.insert(record.mount_id);
+                    continue;
+                }
+                Ok(Presence::Covered) => {
+                    log::warn!(
+                        "Rollback: {} ({}) is covered by another mount, leaving it",
+                        record.mount_point.display(),
+                        record.step
+                    );
+                    failed += 1;
+                    continue;
+                }
+                Ok(Presence::Topmost) => {}
+                Err(e) => {
+                    log::error!("Rollback: failed to read mountinfo: {:#}", e);
+                    failed += 1;
+                    continue;
+                }
+            }
+
+            if let Err(e) = unmount(&record.mount_point, UnmountFlags::DETACH) {
+                log::warn!(
+                    "Rollback: failed to unmount {} ({}): {}",
+                    record.mount_point.display(),
+                    record.step,
+                    e
+                );
+                failed += 1;
+            } else {
+                detached.insert(record.mount_id);
+            }
+        }
+
+        if let Err(e) = journal::forget(&detached) {
+            log::warn!("Failed to drop rolled back mounts from journal: {:#}", e);
+        }
+
+        failed
+    }
+}
@@ -0,0 +1,182 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use procfs::process::{MountInfo, Process};
+use serde::Serialize;
+
+use crate::core::journal::{self, MountRecord};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MountStatus {
+    Present,
+    Missing,
+    Shadowed,
+    WrongType,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MountCheck {
+    pub step: String,
+    pub mount_point: PathBuf,
+    pub fs_type: String,
+    pub modules: Vec<String>,
+    pub status: MountStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub found_fs_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shadowed_by: Option<PathBuf>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DriftReport {
+    pub pid: Option<i32>,
+    pub healthy: bool,
+    pub present: usize,
+    pub missing: usize,
+    pub shadowed: usize,
+    pub wrong_type: usize,
+    pub mounts: Vec<MountCheck>,
+}
+
+struct MountTable {
+    by_id: HashMap<i32, MountInfo>,
+    children: HashMap<i32, Vec<i32>>,
+}
+
+impl MountTable {
+    fn new(mounts: Vec<MountInfo>) -> Self {
+        let mut children: HashMap<i32, Vec<i32>> = HashMap::new();
+        for m in mounts.iter().filter(|m| m.pid != m.mnt_id) {
+            children.entry(m.pid).or_default().push(m.mnt_id);
+        }
+        let by_id = mounts.into_iter().map(|m| (m.mnt_id, m)).collect();
+
+        Self { by_id, children }
+    }
+
+    fn at(&self, path: &Path) -> impl Iterator<Item = &MountInfo> {
+        self.by_id.values().filter(move |m| m.mount_point == path)
+    }
+
+    fn children_of(&self, id: i32) -> impl Iterator<Item = &MountInfo> {
+        self.children
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .filter_map(|c| self.by_id.get(c))
+    }
+
+    // A mount is hidden by anything stacked on its own mount point, or by a sibling that
+    // landed on the same path or one of its parents after it was mounted. The same holds for
+    // every mount between it and the root of the namespace. Returns the covering mount's path.
+    fn covered_by(&self, id: i32) -> Option<PathBuf> {
+        let mut current = self.by_id.get(&id);
+
+        while let Some(m) = current {
+            let on_top = self
+                .children_of(m.mnt_id)
+                .find(|c| c.mount_point == m.mount_point);
+            let sibling = self
+                .children_of(m.pid)
+                .find(|s| s.mnt_id != m.mnt_id && m.mount_point.starts_with(&s.mount_point));
+
+            if let Some(cover) = on_top.or(sibling) {
+                return Some(cover.mount_point.clone());
+            }
+            if m.pid == m.mnt_id {
+                break;
+            }
+            current = self.by_id.get(&m.pid);
+        }
+
+        None
+    }
+
+    fn visible(&self, id: i32) -> bool {
+        self.covered_by(id).is_none()
+    }
+}
+
+// Mount IDs are only meaningful in our own namespace; another process sees copies with new
+// IDs, so there the journal entry is matched by mount point and filesystem type instead.
+fn check(table: &MountTable, record: MountRecord, same_ns: bool) -> MountCheck {
+    let ours = if same_ns {
+        table
+            .by_id
+            .get(&record.mount_id)
+            .filter(|m| m.mount_point == record.mount_point)
+    } else {
+        table
+            .at(&record.mount_point)
+            .filter(|m| m.fs_type == record.fs_type)
+            .max_by_key(|m| table.visible(m.mnt_id))
+    };
+
+    let (status, found_fs_type, shadowed_by) = match ours {
+        Some(m) => match table.covered_by(m.mnt_id) {
+            Some(cover) => (MountStatus::Shadowed, None, Some(cover)),
+            None => (MountStatus::Present, None, None),
+        },
+        None => match table
+            .at(&record.mount_point)
+            .find(|m| table.visible(m.mnt_id) && m.fs_type != record.fs_type)
+        {
+            Some(foreign) => (MountStatus::WrongType, Some(foreign.fs_type.clone()), None),
+            None => (MountStatus::Missing, None, None),
+        },
+    };
+
+    MountCheck {
+        step: record.step,
+        mount_point: record.mount_point,
+        fs_type: record.fs_type,
+        modules: record.modules,
+        status,
+        found_fs_type,
+        shadowed_by,
+    }
+}
+
+pub fn verify(pid: Option<i32>) -> Result<DriftReport> {
+    let process = match pid {
+        Some(pid) => Process::new(pid).with_context(|| format!("No such process: {}", pid))?,
+        None => Process::myself()?,
+    };
+    let same_ns = match pid {
+        Some(pid) => fs::read_link(format!("/proc/{}/ns/mnt", pid))
+            .ok()
+            .is_some_and(|ns| fs::read_link("/proc/self/ns/mnt").ok() == Some(ns)),
+        None => true,
+    };
+
+    let table = MountTable::new(process.mountinfo().context("Failed to read mountinfo")?.0);
+
+    let mounts: Vec<MountCheck> = journal::load()?
+        .into_iter()
+        .map(|record| check(&table, record, same_ns))
+        .collect();
+
+    let count = |status| mounts.iter().filter(|m| m.status == status).count();
+    let present = count(MountStatus::Present);
+    let missing = count(MountStatus::Missing);
+    let shadowed = count(MountStatus::Shadowed);
+    let wrong_type = count(MountStatus::WrongType);
+
+    Ok(DriftReport {
+        pid,
+        healthy: present == mounts.len(),
+        present,
+        missing,
+        shadowed,
+        wrong_type,
+        mounts,
+    })
+}
@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use std::{
+    collections::BTreeMap,
     fs,
     path::PathBuf,
     time::{SystemTime, UNIX_EPOCH},
@@ -12,6 +13,48 @@ use serde::{Deserialize, Serialize};
 
 use crate::defs;
 
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DegradeStrategy {
+    Stacked,
+    MagicFallback,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DegradedMount {
+    pub target: String,
+    pub strategy: DegradeStrategy,
+    pub modules: Vec<String>,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FootprintStats {
+    // Allocated size, which is what a module costs in tmpfs RAM or on the image.
+    pub bytes: u64,
+    pub files: u64,
+    pub dirs: u64,
+    pub symlinks: u64,
+    pub whiteouts: u64,
+}
+
+impl FootprintStats {
+    pub fn add(&mut self, other: &Self) {
+        self.bytes += other.bytes;
+        self.files += other.files;
+        self.dirs += other.dirs;
+        self.symlinks += other.symlinks;
+        self.whiteouts += other.whiteouts;
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModuleStats {
+    #[serde(flatten)]
+    pub total: FootprintStats,
+    pub partitions: BTreeMap<String, FootprintStats>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct RuntimeState {
     pub timestamp: u64,
@@ -30,6 +73,16 @@ pub struct RuntimeState {
     pub storage_percent: u8,
     #[serde(default)]
     pub zygisksu_enforce: bool,
+    #[serde(default)]
+    pub degraded_mounts: Vec<DegradedMount>,
+    #[serde(default)]
+    pub module_stats: BTreeMap<String, ModuleStats>,
+    #[serde(default)]
+    pub failed_step: Option<String>,
+    #[serde(default)]
+    pub failure_reason: Option<String>,
+    #[serde(default)]
+    pub rolled_back: bool,
 }
 
 impl RuntimeState {
@@ -41,6 +94,7 @@ impl RuntimeState {
         magic_modules: Vec<String>,
         active_mounts: Vec<String>,
         storage_info: (u64, u64, u8),
+        degraded_mounts: Vec<DegradedMount>,
     ) -> Self {
         let start = SystemTime::now();
 
@@ -65,6 +119,11 @@ impl RuntimeState {
             storage_used: storage_info.1,
             storage_percent: storage_info.2,
             zygisksu_enforce,
+            degraded_mounts,
+            module_stats: BTreeMap::new(),
+            failed_step: None,
+            failure_reason: None,
+            rolled_back: false,
         }
     }
 
@@ -16,10 +16,13 @@ use serde::Serialize;
 #[cfg(any(target_os = "linux", target_os = "android"))]
 use crate::mount::umount_mgr::send_umountable;
 use crate::{
-    core::state::RuntimeState,
+    core::{
+        journal,
+        state::{ModuleStats, RuntimeState},
+    },
     defs,
     mount::overlayfs::utils as overlay_utils,
-    sys::mount::is_mounted,
+    sys::{mount::is_mounted, probe},
     utils::{self, ensure_dir_exists, lsetfilecon},
 };
 
@@ -48,8 +51,24 @@ impl StorageHandle {
             create_erofs_image(&self.mount_point, image_path)
                 .context("Failed to pack EROFS image")?;
 
-            if let Err(e) = umount(&self.mount_point, UnmountFlags::DETACH) {
-                log::warn!("Failed to unmount staging tmpfs: {}", e);
+            match umount(&self.mount_point, UnmountFlags::DETACH) {
+                // The storage step journaled the staging tmpfs; left there it would read as a
+                // missing mount from now on.
+                Ok(()) => {
+                    let staged = journal::load().and_then(|records| {
+                        journal::forget(
+                            &records
+                                .iter()
+                                .filter(|r| r.mount_point == self.mount_point)
+                                .map(|r| r.mount_id)
+                                .collect(),
+                        )
+                    });
+                    if let Err(e) = staged {
+                        log::warn!("Failed to drop staging tmpfs from journal: {:#}", e);
+                    }
+                }
+                Err(e) => log::warn!("Failed to unmount staging tmpfs: {}", e),
             }
 
             if let Err(e) = fs::remove_dir(&self.mount_point) {
@@ -88,6 +107,14 @@ struct StorageStatus {
     total_size: u64,
     used_size: u64,
     supported_modes: Vec<String>,
+    modules: Vec<ModuleUsage>,
+}
+
+#[derive(Serialize)]
+struct ModuleUsage {
+    id: String,
+    #[serde(flatten)]
+    stats: ModuleStats,
 }
 
 pub fn get_usage(path: &Path) -> (u64, u64, u8) {
@@ -169,7 +196,7 @@ pub fn setup(
 
     if use_erofs && is_erofs_supported() {
         let erofs_path = img_path.with_extension("erofs");
-        let staging_dir = Path::new(defs::RUN_DIR).join("erofs_staging");
+        let staging_dir = PathBuf::from(defs::EROFS_STAGING_DIR);
 
         if is_mounted(&staging_dir) {
             let _ = umount(&staging_dir, UnmountFlags::DETACH);
@@ -222,8 +249,12 @@ pub fn setup(
 
 fn try_setup_tmpfs(target: &Path, mount_source: &str) -> Result<bool> {
     if crate::sys::mount::mount_tmpfs(target, mount_source).is_ok() {
-        if utils::is_overlay_xattr_supported().unwrap_or(false) {
-            log::info!("Tmpfs mounted and supports xattrs (CONFIG_TMPFS_XATTR=y).");
+        let caps = probe::capabilities();
+        if caps.tmpfs_xattr {
+            log::info!("Tmpfs mounted and supports trusted xattrs.");
+            return Ok(true);
+        } else if caps.rootless_overlay() {
+            log::info!("Tmpfs mounted; overlays use user xattrs in this user namespace.");
             return Ok(true);
         } else {
             let _ = umount(target, UnmountFlags::DETACH);
@@ -328,16 +359,23 @@ pub fn print_status() -> Result<()> {
         percent = (used * 100).checked_div(total).unwrap_or(0) as u8;
     }
 
-    let mut supported_modes = vec!["ext4".to_string(), "erofs".to_string()];
-    let check_dir = Path::new(defs::XATTR_CHECK_DIR);
-    if crate::sys::mount::mount_tmpfs(check_dir, "mh_check").is_ok() {
-        if utils::is_overlay_xattr_supported().unwrap_or(false) {
-            supported_modes.insert(0, "tmpfs".to_string());
-        }
-        let _ = umount(check_dir, UnmountFlags::DETACH);
-        let _ = fs::remove_dir(check_dir);
+    let mut supported_modes = vec!["ext4".to_string()];
+    if is_erofs_supported() {
+        supported_modes.push("erofs".to_string());
+    }
+    if probe::capabilities().tmpfs_xattr || probe::capabilities().rootless_overlay() {
+        supported_modes.insert(0, "tmpfs".to_string());
     }
 
+    // Largest first, so whatever is filling the backend shows up at the top.
+    let mut modules: Vec<ModuleUsage> = state
+        .map(|s| s.module_stats)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(id, stats)| ModuleUsage { id, stats })
+        .collect();
+    modules.sort_by_key(|m| std::cmp::Reverse(m.stats.total.bytes));
+
     let status = StorageStatus {
         mode,
         mount_point: mnt_base.to_string_lossy().to_string(),
@@ -345,6 +383,7 @@ pub fn print_status() -> Result<()> {
         total_size: total,
         used_size: used,
         supported_modes,
+        modules,
     };
 
     println!("{}", serde_json::to_string(&status)?);
@@ -353,9 +392,8 @@ pub fn print_status() -> Result<()> {
 }
 
 fn is_erofs_supported() -> bool {
-    fs::read_to_string("/proc/filesystems")
-        .map(|content| content.contains("erofs"))
-        .unwrap_or(false)
+    let caps = probe::capabilities();
+    caps.erofs && caps.loop_device
 }
 
 fn create_erofs_image(src_dir: &Path, image_path: &Path) -> Result<()> {
@@ -387,7 +425,9 @@ fn create_erofs_image(src_dir: &Path, image_path: &Path) -> Result<()> {
     }
 
     let _ = fs::set_permissions(image_path, fs::Permissions::from_mode(0o644));
-    lsetfilecon(image_path, "u:object_r:ksu_file:s0")?;
+    if let Err(e) = lsetfilecon(image_path, "u:object_r:ksu_file:s0") {
+        log::warn!("{:#}", e);
+    }
     Ok(())
 }
 
@@ -5,9 +5,23 @@ pub const DEFAULT_HYBRID_MNT_DIR: &str = "/debug_ramdisk";
 pub const MODULES_IMG_FILE: &str = "/data/adb/meta-hybrid/modules.img";
 pub const RUN_DIR: &str = "/data/adb/meta-hybrid/run/";
 pub const STATE_FILE: &str = "/data/adb/meta-hybrid/run/daemon_state.json";
+pub const MOUNT_JOURNAL_FILE: &str = "/data/adb/meta-hybrid/run/mount_journal.jsonl";
+pub const CAPABILITIES_FILE: &str = "/data/adb/meta-hybrid/run/capabilities.json";
+pub const PROBE_DIR: &str = "/data/adb/meta-hybrid/run/probe";
+pub const PROBE_LOCK_FILE: &str = "/data/adb/meta-hybrid/run/probe.lock";
+pub const CONTROL_SOCKET: &str = "/data/adb/meta-hybrid/run/control.sock";
+pub const EROFS_STAGING_DIR: &str = "/data/adb/meta-hybrid/run/erofs_staging";
+pub const OVERLAY_STACK_DIR: &str = "/data/adb/meta-hybrid/run/stack";
 pub const DISABLE_FILE_NAME: &str = "disable";
 pub const REMOVE_FILE_NAME: &str = "remove";
 pub const SKIP_MOUNT_FILE_NAME: &str = "skip_mount";
+// Files outside the partition trees that the mount backends read from the synced copy.
+pub const SYNC_METADATA_FILES: &[&str] = &[
+    "module.prop",
+    DISABLE_FILE_NAME,
+    REMOVE_FILE_NAME,
+    SKIP_MOUNT_FILE_NAME,
+];
 pub const SYSTEM_RW_DIR: &str = "/data/adb/meta-hybrid/rw";
 pub const MODULE_PROP_FILE: &str = "/data/adb/modules/meta-hybrid/module.prop";
 pub const MODULES_DIR: &str = "/data/adb/modules";
@@ -16,7 +30,6 @@ pub const BACKUPS_DIR: &str = "/data/adb/meta-hybrid/backups";
 pub const BOOT_COUNTER_FILE: &str = "/data/adb/meta-hybrid/run/boot_counter";
 pub const RESCUE_NOTICE_FILE: &str = "/data/adb/meta-hybrid/run/rescue_notice";
 pub const MKFS_EROFS_PATH: &str = "/data/adb/metamodule/tools/mkfs.erofs";
-pub const XATTR_CHECK_DIR: &str = "/data/local/tmp/.mh_xattr_chk";
 pub const POACEAE_MOUNT_POINT: &str = "/data/adb/poaceaefs_mount";
 pub const ZYGISKSU_DENYLIST_FILE: &str = "/data/adb/zygisksu/denylist_enforce";
 
@@ -68,4 +81,6 @@ pub const SENSITIVE_PARTITIONS: &[&str] = &[
 ];
 
 pub const REPLACE_DIR_FILE_NAME: &str = ".replace";
+pub const SYNC_MANIFEST_FILE_NAME: &str = ".hybrid_manifest.json";
 pub const REPLACE_DIR_XATTR: &str = "trusted.overlay.opaque";
+pub const USER_REPLACE_DIR_XATTR: &str = "user.overlay.opaque";
@@ -8,8 +8,12 @@ mod mount;
 mod sys;
 mod utils;
 
-use core::{MountController, ops::backup as granary};
-use std::path::PathBuf;
+use core::{
+    MountController,
+    error::{ErrorKind, TypedError},
+    ops::backup as granary,
+};
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
 use clap::Parser;
@@ -60,6 +64,37 @@ fn load_final_config(cli: &Cli) -> Result<Config> {
     Ok(config)
 }
 
+fn run_command(cli: &Cli, command: &Commands) -> Result<()> {
+    match command {
+        Commands::GenConfig { output } => cli_handlers::handle_gen_config(output),
+        Commands::ShowConfig => cli_handlers::handle_show_config(cli),
+        Commands::SaveConfig { payload } => cli_handlers::handle_save_config(cli, payload),
+        Commands::SaveModuleRules { module, payload } => {
+            cli_handlers::handle_save_module_rules(module, payload)
+        }
+        Commands::Storage => cli_handlers::handle_storage(),
+        Commands::Modules => cli_handlers::handle_modules(cli),
+        Commands::Module { action } => cli_handlers::handle_module(cli, action),
+        Commands::Conflicts { duplicates } => cli_handlers::handle_conflicts(cli, *duplicates),
+        Commands::Diagnostics => cli_handlers::handle_diagnostics(cli),
+        Commands::Doctor { refresh } => cli_handlers::handle_doctor(*refresh),
+        Commands::Plan => cli_handlers::handle_plan(cli),
+        Commands::Explain { path } => cli_handlers::handle_explain(cli, path),
+        Commands::Status { verify, pid } => cli_handlers::handle_status(*verify, *pid),
+        Commands::Relabel { module, dry_run } => {
+            cli_handlers::handle_relabel(cli, module.as_deref(), *dry_run)
+        }
+        Commands::Teardown => cli_handlers::handle_teardown(),
+        Commands::Reload { module } => cli_handlers::handle_reload(cli, module),
+        Commands::Snapshot { action } => cli_handlers::handle_snapshot(cli, action),
+        Commands::Serve {
+            socket,
+            drift_interval,
+        } => cli_handlers::handle_serve(cli, socket, *drift_interval),
+        Commands::Poaceae { target, action } => cli_handlers::handle_poaceae(target, action),
+    }
+}
+
 fn main() -> Result<()> {
     // [Change] Create RUN_DIR immediately as it now hosts critical state files (boot_counter)
     utils::ensure_dir_exists(defs::RUN_DIR)
@@ -75,22 +110,17 @@ fn main() -> Result<()> {
 
     let cli = Cli::parse();
 
+    if cli.sysroot.is_some() && !cli.command.as_ref().is_some_and(Commands::uses_sysroot) {
+        let e = TypedError::new(
+            ErrorKind::ConfigInvalid,
+            "--sysroot only applies to plan, conflicts, diagnostics, explain and relabel",
+        );
+        std::process::exit(core::error::report(&e.into()));
+    }
+
     if let Some(command) = &cli.command {
-        match command {
-            Commands::GenConfig { output } => cli_handlers::handle_gen_config(output)?,
-            Commands::ShowConfig => cli_handlers::handle_show_config(&cli)?,
-            Commands::SaveConfig { payload } => cli_handlers::handle_save_config(&cli, payload)?,
-            Commands::SaveModuleRules { module, payload } => {
-                cli_handlers::handle_save_module_rules(module, payload)?
-            }
-            Commands::Storage => cli_handlers::handle_storage()?,
-            Commands::Modules => cli_handlers::handle_modules(&cli)?,
-            Commands::Conflicts => cli_handlers::handle_conflicts(&cli)?,
-            Commands::Diagnostics => cli_handlers::handle_diagnostics(&cli)?,
-            Commands::SystemAction { action, value } => {
-                cli_handlers::handle_system_action(&cli, action, value.as_deref())?
-            }
-            Commands::Poaceae { target, action } => cli_handlers::handle_poaceae(target, action)?,
+        if let Err(e) = run_command(&cli, command) {
+            std::process::exit(core::error::report(&e));
         }
 
         return Ok(());
@@ -130,6 +160,15 @@ fn main() -> Result<()> {
 
     utils::init_logging(config.verbose).context("Failed to initialize logging")?;
 
+    // Boot mounts onto the live system, so it never resolves paths inside a configured image.
+    if config.sysroot != Path::new("/") {
+        log::warn!(
+            "!! Ignoring sysroot {} at boot; mounting against /",
+            config.sysroot.display()
+        );
+        config.sysroot = PathBuf::from("/");
+    }
+
     let camouflage_name = utils::random_kworker_name();
 
     if let Err(e) = utils::camouflage_process(&camouflage_name) {
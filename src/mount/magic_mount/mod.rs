@@ -1,10 +1,9 @@
 // Copyright 2026 Hybrid Mount Developers
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-mod utils;
+pub mod utils;
 
 use std::{
-    collections::HashSet,
     fs,
     path::{Path, PathBuf},
     sync::atomic::AtomicU32,
@@ -23,6 +22,7 @@ use crate::{
         magic_mount::utils::{clone_symlink, collect_module_files, mount_mirror},
         node::{Node, NodeFileType},
     },
+    sys::sysroot::SysRoot,
     utils::ensure_dir_exists,
 };
 
@@ -306,14 +306,20 @@ pub fn magic_mount<P>(
     module_dir: &Path,
     mount_source: &str,
     extra_partitions: &[String],
-    need_id: HashSet<String>,
+    need_id: Vec<(String, Vec<PathBuf>)>,
     #[cfg(any(target_os = "linux", target_os = "android"))] umount: bool,
     #[cfg(not(any(target_os = "linux", target_os = "android")))] _umount: bool,
 ) -> Result<()>
 where
     P: AsRef<Path>,
 {
-    if let Some(root) = collect_module_files(module_dir, extra_partitions, need_id)? {
+    // Magic Mount always binds onto the running system, so the tree follows its layout too.
+    if let Some(root) = collect_module_files(
+        module_dir,
+        extra_partitions,
+        &need_id,
+        SysRoot::new(Path::new("/")),
+    )? {
         log::debug!("collected: {root:?}");
         let tmp_root = tmp_path.as_ref();
         let tmp_dir = tmp_root.join("workdir");
@@ -11,15 +11,34 @@ use std::{
 use anyhow::{Result, bail};
 use rustix::{
     fs::{Gid, Mode, Uid, chmod, chown},
+    io::Errno,
     mount::mount_bind,
 };
 
 use crate::{
     defs::{DISABLE_FILE_NAME, REMOVE_FILE_NAME, SKIP_MOUNT_FILE_NAME},
     mount::node::Node,
+    sys::{probe, sysroot::SysRoot},
     utils::{lgetfilecon, lsetfilecon, validate_module_id},
 };
 
+// Only an unlabeled source, or a system that cannot label files at all, leaves nothing to copy;
+// any other failure would give the mirror the wrong label.
+fn clone_filecon(src: &Path, dst: &Path) -> Result<()> {
+    match lgetfilecon(src) {
+        Ok(con) => lsetfilecon(dst, &con),
+        Err(e)
+            if !probe::capabilities().selinux_label
+                || rustix::fs::lgetxattr(src, "security.selinux", &mut [0u8; 0])
+                    == Err(Errno::NODATA) =>
+        {
+            log::debug!("{} has no label to copy: {:#}", src.display(), e);
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
+
 fn metadata_path<P>(path: P, node: &Node) -> Result<(Metadata, PathBuf)>
 where
     P: AsRef<Path>,
@@ -55,7 +74,7 @@ where
         Some(Uid::from_raw(metadata.uid())),
         Some(Gid::from_raw(metadata.gid())),
     )?;
-    lsetfilecon(work_dir_path, lgetfilecon(path)?.as_str())?;
+    clone_filecon(&path, work_dir_path)?;
 
     Ok(())
 }
@@ -90,7 +109,7 @@ where
             Some(Uid::from_raw(metadata.uid())),
             Some(Gid::from_raw(metadata.gid())),
         )?;
-        lsetfilecon(&work_dir_path, lgetfilecon(&path)?.as_str())?;
+        clone_filecon(&path, &work_dir_path)?;
         for entry in path.read_dir()?.flatten() {
             mount_mirror(&path, &work_dir_path, &entry)?;
         }
@@ -106,10 +125,12 @@ where
     Ok(())
 }
 
+// Modules are collected in the order given: the first module to supply a file keeps it.
 pub fn collect_module_files(
     module_dir: &Path,
     extra_partitions: &[String],
-    need_id: HashSet<String>,
+    need_id: &[(String, Vec<PathBuf>)],
+    sysroot: SysRoot,
 ) -> Result<Option<Node>> {
     let mut root = Node::new_root("");
     let mut system = Node::new_root("system");
@@ -118,20 +139,16 @@ pub fn collect_module_files(
 
     log::debug!("begin collect module files: {}", module_root.display());
 
-    for entry in module_root.read_dir()?.flatten() {
-        if !entry.file_type()?.is_dir() {
+    for (id, scope) in need_id {
+        let module_path = module_root.join(id);
+        if !module_path.is_dir() {
+            log::debug!("module {id} not found in {}", module_root.display());
             continue;
         }
 
-        let id = entry.file_name().to_str().unwrap().to_string();
         log::debug!("processing new module: {id}");
 
-        if !need_id.contains(&id) {
-            log::debug!("module {id} was blocked.");
-            continue;
-        }
-
-        let prop = entry.path().join("module.prop");
+        let prop = module_path.join("module.prop");
         if !prop.exists() {
             log::debug!("skipped module {id}, because not found module.prop");
             continue;
@@ -145,21 +162,24 @@ pub fn collect_module_files(
             }
         }
 
-        if entry.path().join(DISABLE_FILE_NAME).exists()
-            || entry.path().join(REMOVE_FILE_NAME).exists()
-            || entry.path().join(SKIP_MOUNT_FILE_NAME).exists()
+        if module_path.join(DISABLE_FILE_NAME).exists()
+            || module_path.join(REMOVE_FILE_NAME).exists()
+            || module_path.join(SKIP_MOUNT_FILE_NAME).exists()
         {
             log::debug!("skipped module {id}, due to disable/remove/skip_mount");
             continue;
         }
 
-        let mut modified = false;
-        let mut partitions = HashSet::new();
-        partitions.insert("system".to_string());
-        partitions.extend(extra_partitions.iter().cloned());
+        let mut partitions = vec!["system".to_string()];
+        for p in extra_partitions {
+            if !partitions.contains(p) {
+                partitions.push(p.clone());
+            }
+        }
 
+        let mut modified = false;
         for p in &partitions {
-            if entry.path().join(p).is_dir() {
+            if module_path.join(p).is_dir() {
                 modified = true;
                 break;
             }
@@ -170,14 +190,25 @@ pub fn collect_module_files(
             continue;
         }
 
-        log::debug!("collecting {}", entry.path().display());
+        log::debug!("collecting {}", module_path.display());
 
+        // Other partitions are collected as children of /system and moved to the root below
+        // when the device keeps them there.
         for p in partitions {
-            if !entry.path().join(&p).exists() {
+            let partition_dir = module_path.join(&p);
+            if !partition_dir.is_dir() {
                 continue;
             }
 
-            has_file.insert(system.collect_module_files(entry.path().join(&p))?);
+            let node = if p == "system" {
+                &mut system
+            } else {
+                system
+                    .children
+                    .entry(p.clone())
+                    .or_insert_with(|| Node::new_root(p.clone()))
+            };
+            has_file.insert(node.collect_module_files(&partition_dir, Path::new(&p), scope)?);
         }
     }
 
@@ -192,7 +223,9 @@ pub fn collect_module_files(
         for (partition, require_symlink) in BUILTIN_PARTITIONS {
             let path_of_root = Path::new("/").join(partition);
             let path_of_system = Path::new("/system").join(partition);
-            if path_of_root.is_dir() && (!require_symlink || path_of_system.is_symlink()) {
+            if sysroot.is_dir(&path_of_root)
+                && (!require_symlink || sysroot.is_symlink(&path_of_system))
+            {
                 let name = partition.to_string();
                 if let Some(node) = system.children.remove(&name) {
                     root.children.insert(name, node);
@@ -212,7 +245,9 @@ pub fn collect_module_files(
             let path_of_system = Path::new("/system").join(partition);
             let require_symlink = false;
 
-            if path_of_root.is_dir() && (!require_symlink || path_of_system.is_symlink()) {
+            if sysroot.is_dir(&path_of_root)
+                && (!require_symlink || sysroot.is_symlink(&path_of_system))
+            {
                 let name = partition.clone();
                 if let Some(node) = system.children.remove(&name) {
                     log::debug!("attach extra partition '{name}' to root");
@@ -234,7 +269,7 @@ where
 {
     let src_symlink = read_link(src.as_ref())?;
     symlink(&src_symlink, dst.as_ref())?;
-    lsetfilecon(dst.as_ref(), lgetfilecon(src.as_ref())?.as_str())?;
+    clone_filecon(src.as_ref(), dst.as_ref())?;
     log::debug!(
         "clone symlink {} -> {}({})",
         dst.as_ref().display(),
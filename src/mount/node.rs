@@ -12,7 +12,7 @@ use std::{
 use anyhow::Result;
 use extattr::lgetxattr;
 
-use crate::defs::{REPLACE_DIR_FILE_NAME, REPLACE_DIR_XATTR};
+use crate::{defs::REPLACE_DIR_FILE_NAME, sys::probe};
 
 #[derive(PartialEq, Eq, Hash, Clone, Debug)]
 pub enum NodeFileType {
@@ -57,7 +57,14 @@ impl fmt::Display for Node {
 }
 
 impl Node {
-    pub fn collect_module_files<P>(&mut self, module_dir: P) -> Result<bool>
+    // `scope` lists the module-relative subtrees that may be collected; directories leading
+    // to a scoped subtree are walked but never marked as replaced.
+    pub fn collect_module_files<P>(
+        &mut self,
+        module_dir: P,
+        relative: &Path,
+        scope: &[PathBuf],
+    ) -> Result<bool>
     where
         P: AsRef<Path>,
     {
@@ -65,18 +72,40 @@ impl Node {
         let mut has_file = false;
         for entry in dir.read_dir()?.flatten() {
             let name = entry.file_name().to_string_lossy().to_string();
+            let entry_relative = relative.join(&name);
 
-            let node = match self.children.entry(name.clone()) {
-                Entry::Occupied(o) => Some(o.into_mut()),
-                Entry::Vacant(v) => Self::new_module(&name, &entry).map(|it| v.insert(it)),
+            let in_scope = scope.iter().any(|root| entry_relative.starts_with(root));
+            if !in_scope {
+                let leads_to_scope = scope.iter().any(|root| root.starts_with(&entry_relative));
+                if !leads_to_scope || !entry.file_type().is_ok_and(|t| t.is_dir()) {
+                    continue;
+                }
+            }
+
+            let (node, created) = match self.children.entry(name.clone()) {
+                Entry::Occupied(o) => (Some(o.into_mut()), false),
+                Entry::Vacant(v) => (
+                    Self::new_module(&name, &entry).map(|mut it| {
+                        it.replace &= in_scope;
+                        v.insert(it)
+                    }),
+                    true,
+                ),
             };
 
             if let Some(node) = node {
-                has_file |= if node.file_type == NodeFileType::Directory {
-                    node.collect_module_files(dir.join(&node.name))? || node.replace
+                let collected = if node.file_type == NodeFileType::Directory {
+                    node.collect_module_files(dir.join(&node.name), &entry_relative, scope)?
+                        || node.replace
                 } else {
                     true
+                };
+
+                if !collected && !in_scope && created {
+                    self.children.remove(&name);
                 }
+
+                has_file |= collected;
             }
         }
 
@@ -87,7 +116,7 @@ impl Node {
     where
         P: AsRef<Path>,
     {
-        if let Ok(v) = lgetxattr(&path, REPLACE_DIR_XATTR)
+        if let Ok(v) = lgetxattr(&path, probe::opaque_xattr())
             && String::from_utf8_lossy(&v) == "y"
         {
             return true;
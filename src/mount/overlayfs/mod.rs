@@ -117,5 +117,5 @@ where
         upperdir = Some(system_rw_dir.join(partition_name).join("upperdir"));
     }
 
-    overlayfs::mount_overlay(&partition, lowerdir, workdir, upperdir, mount_source)
+    overlayfs::mount_overlay(&partition, lowerdir, workdir, upperdir, mount_source).map(|_| ())
 }
@@ -3,8 +3,10 @@
 
 use std::{
     ffi::CString,
+    fs,
     os::fd::AsFd,
     path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
 use anyhow::{Context, Result, bail};
@@ -12,75 +14,102 @@ use procfs::process::Process;
 use rustix::{
     fs::CWD,
     mount::{
-        FsMountFlags, FsOpenFlags, MountAttrFlags, MountFlags, MoveMountFlags, fsconfig_create,
-        fsconfig_set_string, fsmount, fsopen, mount, move_mount,
+        FsMountFlags, FsOpenFlags, MountAttrFlags, MountFlags, MoveMountFlags, UnmountFlags,
+        fsconfig_create, fsconfig_set_flag, fsconfig_set_string, fsmount, fsopen, mount,
+        move_mount, unmount,
     },
 };
 
-use crate::mount::{overlayfs::utils::umount_dir, umount_mgr::send_umountable};
+use crate::{
+    defs,
+    mount::{overlayfs::utils::umount_dir, umount_mgr::send_umountable},
+    sys::probe,
+};
 
 const MAX_LOWERDIR_COUNT: usize = 128;
 const MAX_ARG_LENGTH: usize = 3000;
+// OVL_MAX_STACK: the most layers a single overlay accepts, even through `lowerdir+`.
+const MAX_STACK_LAYERS: usize = 500;
 
-pub fn mount_overlayfs(
-    lower_dirs: &[String],
-    lowest: &str,
-    upperdir: Option<PathBuf>,
-    workdir: Option<PathBuf>,
-    dest: impl AsRef<Path>,
-    mount_source: &str,
-) -> Result<()> {
-    let mut valid_lower_dirs: Vec<&str> = lower_dirs
-        .iter()
-        .map(|s| s.as_str())
-        .chain(std::iter::once(lowest))
-        .collect();
-
-    if valid_lower_dirs.len() > MAX_LOWERDIR_COUNT {
-        log::warn!(
-            "Too many overlay layers ({} > {}). Truncating to prevent failure. Some modules may not load.",
-            valid_lower_dirs.len(),
-            MAX_LOWERDIR_COUNT
-        );
-        valid_lower_dirs.truncate(MAX_LOWERDIR_COUNT);
-    }
+static STACK_SEQ: AtomicUsize = AtomicUsize::new(0);
 
-    let mut lowerdir_config = valid_lower_dirs.join(":");
+#[derive(Debug, Default)]
+pub struct OverlayMountReport {
+    pub stacked: bool,
+    pub overflow: Vec<String>,
+}
 
-    if lowerdir_config.len() > MAX_ARG_LENGTH {
-        log::warn!(
-            "OverlayFS lowerdir argument too long ({} bytes). Truncating...",
-            lowerdir_config.len()
-        );
-        while lowerdir_config.len() > MAX_ARG_LENGTH && valid_lower_dirs.len() > 1 {
-            valid_lower_dirs.pop();
-            lowerdir_config = valid_lower_dirs.join(":");
+impl OverlayMountReport {
+    fn merge(&mut self, other: Self) {
+        self.stacked |= other.stacked;
+        for layer in other.overflow {
+            if !self.overflow.contains(&layer) {
+                self.overflow.push(layer);
+            }
         }
     }
+}
 
-    log::info!(
-        "mount overlayfs on {:?}, layers={}, upperdir={:?}, workdir={:?}, source={}",
-        dest.as_ref(),
-        valid_lower_dirs.len(),
-        upperdir,
-        workdir,
-        mount_source
-    );
+// Overlays mounted from an unprivileged user namespace only see opaque markers kept in
+// user.overlay.* xattrs.
+fn use_userxattr() -> bool {
+    probe::capabilities().rootless_overlay()
+}
 
-    let upperdir_s = upperdir
-        .as_ref()
-        .filter(|up| up.exists())
-        .map(|e| e.display().to_string());
-    let workdir_s = workdir
-        .as_ref()
-        .filter(|wd| wd.exists())
-        .map(|e| e.display().to_string());
+fn fits_legacy(layers: &[&str]) -> bool {
+    layers.len() <= MAX_LOWERDIR_COUNT && layers.join(":").len() <= MAX_ARG_LENGTH
+}
 
-    let result = (|| {
+fn mount_per_layer(
+    layers: &[&str],
+    upperdir: Option<&str>,
+    workdir: Option<&str>,
+    dest: &Path,
+    mount_source: &str,
+) -> Result<()> {
+    let fs = fsopen("overlay", FsOpenFlags::FSOPEN_CLOEXEC)?;
+    let fs = fs.as_fd();
+    for layer in layers {
+        fsconfig_set_string(fs, "lowerdir+", *layer)
+            .with_context(|| format!("lowerdir+ rejected {layer}"))?;
+    }
+    if use_userxattr() {
+        fsconfig_set_flag(fs, "userxattr")?;
+    }
+    if let (Some(upperdir), Some(workdir)) = (upperdir, workdir) {
+        fsconfig_set_string(fs, "upperdir", upperdir)?;
+        fsconfig_set_string(fs, "workdir", workdir)?;
+    }
+    fsconfig_set_string(fs, "source", mount_source)?;
+    fsconfig_create(fs)?;
+    let mount = fsmount(fs, FsMountFlags::FSMOUNT_CLOEXEC, MountAttrFlags::empty())?;
+    move_mount(
+        mount.as_fd(),
+        "",
+        CWD,
+        dest,
+        MoveMountFlags::MOVE_MOUNT_F_EMPTY_PATH,
+    )?;
+    Ok(())
+}
+
+fn mount_legacy(
+    layers: &[&str],
+    upperdir: Option<&str>,
+    workdir: Option<&str>,
+    dest: &Path,
+    mount_source: &str,
+) -> Result<()> {
+    let lowerdir_config = layers.join(":");
+
+    let result = probe::capabilities().new_mount_api.then(|| {
         let fs = fsopen("overlay", FsOpenFlags::FSOPEN_CLOEXEC)?;
         let fs = fs.as_fd();
         fsconfig_set_string(fs, "lowerdir", &lowerdir_config)?;
-        if let (Some(upperdir), Some(workdir)) = (&upperdir_s, &workdir_s) {
+        if use_userxattr() {
+            fsconfig_set_flag(fs, "userxattr")?;
+        }
+        if let (Some(upperdir), Some(workdir)) = (upperdir, workdir) {
             fsconfig_set_string(fs, "upperdir", upperdir)?;
             fsconfig_set_string(fs, "workdir", workdir)?;
         }
@@ -91,26 +120,31 @@ pub fn mount_overlayfs(
             mount.as_fd(),
             "",
             CWD,
-            dest.as_ref(),
+            dest,
             MoveMountFlags::MOVE_MOUNT_F_EMPTY_PATH,
         )
-    })();
+    });
 
-    if let Err(e) = result {
-        log::warn!("fsopen mount failed: {:#}, fallback to mount", e);
+    if !matches!(result, Some(Ok(()))) {
+        if let Some(Err(e)) = result {
+            log::warn!("fsopen mount failed: {:#}, fallback to mount", e);
+        }
         let safe_lower = lowerdir_config.replace(',', "\\,");
         let mut data = format!("lowerdir={safe_lower}");
 
-        if let (Some(upperdir), Some(workdir)) = (upperdir_s, workdir_s) {
+        if let (Some(upperdir), Some(workdir)) = (upperdir, workdir) {
             data = format!(
                 "{data},upperdir={},workdir={}",
                 upperdir.replace(',', "\\,"),
                 workdir.replace(',', "\\,")
             );
         }
+        if use_userxattr() {
+            data.push_str(",userxattr");
+        }
         mount(
             mount_source,
-            dest.as_ref(),
+            dest,
             "overlay",
             MountFlags::empty(),
             Some(CString::new(data)?.as_c_str()),
@@ -119,6 +153,173 @@ pub fn mount_overlayfs(
     Ok(())
 }
 
+fn chunk_layers<'a>(layers: &[&'a str]) -> Vec<Vec<&'a str>> {
+    let mut chunks: Vec<Vec<&str>> = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+
+    for layer in layers {
+        current.push(layer);
+        if current.len() > 1 && !fits_legacy(&current) {
+            current.pop();
+            chunks.push(std::mem::take(&mut current));
+            current.push(layer);
+        }
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+
+    chunks
+}
+
+// Mounts each chunk of module layers as a read-only intermediate overlay and stacks those under
+// the final mount. Whiteouts and opaque directories only take effect within their own chunk.
+fn mount_stacked(
+    layers: &[&str],
+    lowest: &str,
+    upperdir: Option<&str>,
+    workdir: Option<&str>,
+    dest: &Path,
+    mount_source: &str,
+) -> Result<()> {
+    let mut staged: Vec<String> = Vec::new();
+    let mut final_layers: Vec<String> = Vec::new();
+
+    let result = (|| {
+        for chunk in chunk_layers(layers) {
+            // An overlay without an upperdir needs at least two lower layers.
+            if chunk.len() < 2 {
+                final_layers.extend(chunk.iter().map(|layer| layer.to_string()));
+                continue;
+            }
+
+            let stage = Path::new(defs::OVERLAY_STACK_DIR)
+                .join(STACK_SEQ.fetch_add(1, Ordering::Relaxed).to_string());
+            fs::create_dir_all(&stage)?;
+            mount_legacy(&chunk, None, None, &stage, mount_source)
+                .with_context(|| format!("failed to stage {}", stage.display()))?;
+            staged.push(stage.display().to_string());
+            final_layers.push(stage.display().to_string());
+        }
+
+        let final_layers: Vec<&str> = final_layers
+            .iter()
+            .map(String::as_str)
+            .chain(std::iter::once(lowest))
+            .collect();
+
+        mount_legacy(&final_layers, upperdir, workdir, dest, mount_source)
+    })();
+
+    // The finished overlay holds private clones of its layers, so the staging mounts can go.
+    for stage in staged.iter().rev() {
+        let _ = unmount(stage.as_str(), UnmountFlags::DETACH);
+        let _ = fs::remove_dir(stage);
+    }
+
+    result
+}
+
+pub fn mount_overlayfs(
+    lower_dirs: &[String],
+    lowest: &str,
+    upperdir: Option<PathBuf>,
+    workdir: Option<PathBuf>,
+    dest: impl AsRef<Path>,
+    mount_source: &str,
+) -> Result<OverlayMountReport> {
+    let dest = dest.as_ref();
+    let module_layers: Vec<&str> = lower_dirs.iter().map(|s| s.as_str()).collect();
+    let all_layers: Vec<&str> = module_layers
+        .iter()
+        .copied()
+        .chain(std::iter::once(lowest))
+        .collect();
+
+    log::info!(
+        "mount overlayfs on {:?}, layers={}, upperdir={:?}, workdir={:?}, source={}",
+        dest,
+        all_layers.len(),
+        upperdir,
+        workdir,
+        mount_source
+    );
+
+    let upperdir_s = upperdir
+        .as_ref()
+        .filter(|up| up.exists())
+        .map(|e| e.display().to_string());
+    let workdir_s = workdir
+        .as_ref()
+        .filter(|wd| wd.exists())
+        .map(|e| e.display().to_string());
+    let upper = upperdir_s.as_deref();
+    let work = workdir_s.as_deref();
+
+    if all_layers.len() <= MAX_STACK_LAYERS && probe::capabilities().overlay_lowerdir_append {
+        match mount_per_layer(&all_layers, upper, work, dest, mount_source) {
+            Ok(()) => return Ok(OverlayMountReport::default()),
+            Err(e) => log::debug!("Per-layer lowerdir+ unavailable: {:#}", e),
+        }
+    }
+
+    if fits_legacy(&all_layers) {
+        mount_legacy(&all_layers, upper, work, dest, mount_source)?;
+        return Ok(OverlayMountReport::default());
+    }
+
+    log::warn!(
+        "{} overlay layers exceed the lowerdir limit ({} layers / {} bytes). Stacking intermediate overlays.",
+        all_layers.len(),
+        MAX_LOWERDIR_COUNT,
+        MAX_ARG_LENGTH
+    );
+
+    match mount_stacked(&module_layers, lowest, upper, work, dest, mount_source) {
+        Ok(()) => {
+            return Ok(OverlayMountReport {
+                stacked: true,
+                overflow: Vec::new(),
+            });
+        }
+        Err(e) => log::warn!("Stacked overlay failed: {:#}", e),
+    }
+
+    let mut keep = module_layers.len();
+    while keep > 0 {
+        let candidate: Vec<&str> = module_layers[..keep]
+            .iter()
+            .copied()
+            .chain(std::iter::once(lowest))
+            .collect();
+        if fits_legacy(&candidate) {
+            break;
+        }
+        keep -= 1;
+    }
+
+    let kept: Vec<&str> = module_layers[..keep]
+        .iter()
+        .copied()
+        .chain(std::iter::once(lowest))
+        .collect();
+    let overflow = lower_dirs[keep..].to_vec();
+
+    log::warn!(
+        "Mounting the top {} layers on {:?}; {} layers are handed to Magic Mount.",
+        keep,
+        dest,
+        overflow.len()
+    );
+
+    mount_legacy(&kept, upper, work, dest, mount_source)?;
+
+    Ok(OverlayMountReport {
+        stacked: false,
+        overflow,
+    })
+}
+
 pub fn bind_mount(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<()> {
     log::info!(
         "bind mount {} -> {}",
@@ -161,30 +362,33 @@ fn mount_overlay_child(
     module_roots: &Vec<String>,
     stock_root: &String,
     mount_source: &str,
-) -> Result<()> {
+) -> Result<OverlayMountReport> {
     if !module_roots
         .iter()
         .any(|lower| Path::new(&format!("{lower}{relative}")).exists())
     {
-        return bind_mount(stock_root, mount_point);
+        bind_mount(stock_root, mount_point)?;
+        return Ok(OverlayMountReport::default());
     }
     if !Path::new(&stock_root).is_dir() {
-        return Ok(());
+        return Ok(OverlayMountReport::default());
     }
     let mut lower_dirs: Vec<String> = vec![];
+    let mut lower_roots: Vec<&String> = vec![];
     for lower in module_roots {
         let lower_dir = format!("{lower}{relative}");
         let path = Path::new(&lower_dir);
         if path.is_dir() {
             lower_dirs.push(lower_dir);
+            lower_roots.push(lower);
         } else if path.exists() {
-            return Ok(());
+            return Ok(OverlayMountReport::default());
         }
     }
     if lower_dirs.is_empty() {
-        return Ok(());
+        return Ok(OverlayMountReport::default());
     }
-    if let Err(e) = mount_overlayfs(
+    let report = match mount_overlayfs(
         &lower_dirs,
         stock_root,
         None,
@@ -192,11 +396,27 @@ fn mount_overlay_child(
         mount_point,
         mount_source,
     ) {
-        log::warn!("failed: {:#}, fallback to bind mount", e);
-        bind_mount(stock_root, mount_point)?;
-    }
+        // Overflow is reported against the module roots the caller passed in, not the child
+        // directories inside them.
+        Ok(report) => OverlayMountReport {
+            stacked: report.stacked,
+            overflow: report
+                .overflow
+                .iter()
+                .filter_map(|dir| {
+                    let index = lower_dirs.iter().position(|lower| lower == dir)?;
+                    Some(lower_roots[index].clone())
+                })
+                .collect(),
+        },
+        Err(e) => {
+            log::warn!("failed: {:#}, fallback to bind mount", e);
+            bind_mount(stock_root, mount_point)?;
+            OverlayMountReport::default()
+        }
+    };
     let _ = send_umountable(mount_point);
-    Ok(())
+    Ok(report)
 }
 
 pub fn mount_overlay(
@@ -205,7 +425,7 @@ pub fn mount_overlay(
     workdir: Option<PathBuf>,
     upperdir: Option<PathBuf>,
     mount_source: &str,
-) -> Result<()> {
+) -> Result<OverlayMountReport> {
     log::info!("mount overlay for {}", root);
     std::env::set_current_dir(root).with_context(|| format!("failed to chdir to {root}"))?;
     let stock_root = ".";
@@ -224,7 +444,7 @@ pub fn mount_overlay(
     mount_seq.sort();
     mount_seq.dedup();
 
-    mount_overlayfs(module_roots, root, upperdir, workdir, root, mount_source)
+    let mut report = mount_overlayfs(module_roots, root, upperdir, workdir, root, mount_source)
         .with_context(|| "mount overlayfs for root failed")?;
     for mount_point in mount_seq.iter() {
         let Some(mount_point) = mount_point else {
@@ -235,21 +455,24 @@ pub fn mount_overlay(
         if !Path::new(&stock_root).exists() {
             continue;
         }
-        if let Err(e) = mount_overlay_child(
+        match mount_overlay_child(
             mount_point,
             &relative,
             module_roots,
             &stock_root,
             mount_source,
         ) {
-            log::warn!(
-                "failed to mount overlay for child {}: {:#}, revert",
-                mount_point,
-                e
-            );
-            umount_dir(root).with_context(|| format!("failed to revert {root}"))?;
-            bail!(e);
+            Ok(child) => report.merge(child),
+            Err(e) => {
+                log::warn!(
+                    "failed to mount overlay for child {}: {:#}, revert",
+                    mount_point,
+                    e
+                );
+                umount_dir(root).with_context(|| format!("failed to revert {root}"))?;
+                bail!(e);
+            }
         }
     }
-    Ok(())
+    Ok(report)
 }
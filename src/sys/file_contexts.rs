@@ -0,0 +1,262 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    os::unix::fs::FileTypeExt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Result, bail};
+use regex_lite::Regex;
+
+// One entry per policy partition, in the order libselinux loads them. Pre-Treble devices keep
+// the files in the ramdisk root instead of the partition's etc/selinux.
+const CONTEXT_FILES: &[&[&str]] = &[
+    &[
+        "system/etc/selinux/plat_file_contexts",
+        "plat_file_contexts",
+    ],
+    &[
+        "system_ext/etc/selinux/system_ext_file_contexts",
+        "system_ext_file_contexts",
+    ],
+    &[
+        "product/etc/selinux/product_file_contexts",
+        "product_file_contexts",
+    ],
+    &[
+        "vendor/etc/selinux/vendor_file_contexts",
+        "vendor_file_contexts",
+    ],
+    &["odm/etc/selinux/odm_file_contexts", "odm_file_contexts"],
+];
+
+static LOADED: Mutex<BTreeMap<PathBuf, Option<Arc<FileContexts>>>> = Mutex::new(BTreeMap::new());
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileKind {
+    Any,
+    File,
+    Dir,
+    Symlink,
+    Char,
+    Block,
+    Socket,
+    Pipe,
+}
+
+impl FileKind {
+    fn from_flag(flag: &str) -> Option<Self> {
+        Some(match flag {
+            "--" => Self::File,
+            "-d" => Self::Dir,
+            "-l" => Self::Symlink,
+            "-c" => Self::Char,
+            "-b" => Self::Block,
+            "-s" => Self::Socket,
+            "-p" => Self::Pipe,
+            _ => return None,
+        })
+    }
+
+    pub fn of(file_type: &fs::FileType) -> Self {
+        if file_type.is_dir() {
+            Self::Dir
+        } else if file_type.is_symlink() {
+            Self::Symlink
+        } else if file_type.is_char_device() {
+            Self::Char
+        } else if file_type.is_block_device() {
+            Self::Block
+        } else if file_type.is_socket() {
+            Self::Socket
+        } else if file_type.is_fifo() {
+            Self::Pipe
+        } else {
+            Self::File
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Spec {
+    regex: Regex,
+    // Literal text every match starts with, used to skip most regexes cheaply.
+    stem: String,
+    exact: bool,
+    kind: FileKind,
+    // None for `<<none>>`, which tells restorecon to leave the path alone.
+    context: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct FileContexts {
+    specs: Vec<Spec>,
+    pub sources: Vec<PathBuf>,
+}
+
+fn is_meta(c: char) -> bool {
+    matches!(
+        c,
+        '.' | '^' | '$' | '?' | '*' | '+' | '|' | '[' | ']' | '(' | ')' | '{' | '}' | '\\'
+    )
+}
+
+// Returns the literal prefix of a spec and whether the spec is literal as a whole. A
+// quantifier makes the character before it optional, and a top-level alternation means
+// no prefix is shared at all.
+fn literal_stem(pattern: &str) -> (String, bool) {
+    let mut depth = 0i32;
+    let mut escaped = false;
+    for c in pattern.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            '|' if depth == 0 => return (String::new(), false),
+            _ => {}
+        }
+    }
+
+    let mut stem = String::new();
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.peek() {
+                Some(&next) if is_meta(next) || next == '/' || next == '-' => {
+                    stem.push(next);
+                    chars.next();
+                    continue;
+                }
+                _ => return (stem, false),
+            }
+        }
+        if is_meta(c) {
+            if matches!(c, '?' | '*' | '{') {
+                stem.pop();
+            }
+            return (stem, false);
+        }
+        stem.push(c);
+    }
+
+    (stem, true)
+}
+
+impl FileContexts {
+    // Loads every file_contexts the device ships under `root`. Fails only when none is found.
+    pub fn load(root: &Path) -> Result<Self> {
+        let mut contexts = Self::default();
+
+        for candidates in CONTEXT_FILES {
+            let Some(path) = candidates
+                .iter()
+                .map(|c| root.join(c))
+                .find(|p| p.is_file())
+            else {
+                continue;
+            };
+            match fs::read_to_string(&path) {
+                Ok(content) => {
+                    contexts.parse(&content, &path);
+                    contexts.sources.push(path);
+                }
+                Err(e) => log::warn!("Failed to read {}: {}", path.display(), e),
+            }
+        }
+
+        if contexts.sources.is_empty() {
+            bail!("No file_contexts found under {}", root.display());
+        }
+
+        log::debug!(
+            "Loaded {} file_contexts specs from {} files",
+            contexts.specs.len(),
+            contexts.sources.len()
+        );
+        Ok(contexts)
+    }
+
+    // Malformed lines and regexes the engine cannot compile are skipped with a debug log, like
+    // libselinux does for entries it does not understand.
+    pub fn parse(&mut self, content: &str, source: &Path) {
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (pattern, kind, context) = match fields.as_slice() {
+                [pattern, context] => (*pattern, FileKind::Any, *context),
+                [pattern, flag, context] => match FileKind::from_flag(flag) {
+                    Some(kind) => (*pattern, kind, *context),
+                    None => {
+                        log::debug!(
+                            "{}:{}: unknown file type {}",
+                            source.display(),
+                            index + 1,
+                            flag
+                        );
+                        continue;
+                    }
+                },
+                _ => {
+                    log::debug!("{}:{}: malformed entry", source.display(), index + 1);
+                    continue;
+                }
+            };
+
+            let regex = match Regex::new(&format!("^(?:{})$", pattern)) {
+                Ok(regex) => regex,
+                Err(e) => {
+                    log::debug!("{}:{}: {}", source.display(), index + 1, e);
+                    continue;
+                }
+            };
+            let (stem, exact) = literal_stem(pattern);
+
+            self.specs.push(Spec {
+                regex,
+                stem,
+                exact,
+                kind,
+                context: (context != "<<none>>").then(|| context.to_string()),
+            });
+        }
+    }
+
+    // Picks the most specific matching spec: literal specs first, then the longest literal
+    // prefix, and among equals the one loaded last, so vendor entries override platform ones.
+    pub fn lookup(&self, path: &Path, kind: FileKind) -> Option<&str> {
+        let path = path.to_string_lossy();
+
+        self.specs
+            .iter()
+            .enumerate()
+            .filter(|(_, spec)| spec.kind == FileKind::Any || spec.kind == kind)
+            .filter(|(_, spec)| path.starts_with(&spec.stem) && spec.regex.is_match(&path))
+            .max_by_key(|(order, spec)| (spec.exact, spec.stem.len(), *order))
+            .and_then(|(_, spec)| spec.context.as_deref())
+    }
+}
+
+// The file_contexts of a system root, loaded on first use. None when it ships none we can read.
+pub fn for_root(root: &Path) -> Option<Arc<FileContexts>> {
+    let mut loaded = LOADED.lock().unwrap_or_else(|e| e.into_inner());
+
+    loaded
+        .entry(root.to_path_buf())
+        .or_insert_with(|| match FileContexts::load(root) {
+            Ok(contexts) => Some(Arc::new(contexts)),
+            Err(e) => {
+                log::debug!("file_contexts unavailable: {:#}", e);
+                None
+            }
+        })
+        .clone()
+}
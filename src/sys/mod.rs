@@ -1,5 +1,8 @@
 // Copyright 2026 Hybrid Mount Developers
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+pub mod file_contexts;
 pub mod mount;
 pub mod poaceae;
+pub mod probe;
+pub mod sysroot;
@@ -0,0 +1,314 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    os::fd::AsFd,
+    path::Path,
+    sync::OnceLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result, bail};
+use rustix::{
+    fs::{FlockOperation, flock},
+    mount::{
+        FsMountFlags, FsOpenFlags, MountAttrFlags, MountFlags, MountPropagationFlags, UnmountFlags,
+        fsconfig_create, fsconfig_set_string, fsmount, fsopen, mount, mount_change, unmount,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{defs, utils};
+
+const PROBE_CONTEXT: &str = "u:object_r:system_file:s0";
+
+static CAPABILITIES: OnceLock<Capabilities> = OnceLock::new();
+static USER_NAMESPACE: OnceLock<bool> = OnceLock::new();
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub kernel_release: String,
+    pub probed_at: u64,
+    pub tmpfs_xattr: bool,
+    pub overlayfs: bool,
+    pub new_mount_api: bool,
+    pub overlay_lowerdir_append: bool,
+    pub erofs: bool,
+    pub loop_device: bool,
+    pub user_xattr: bool,
+    pub selinux_label: bool,
+    #[serde(default)]
+    pub overlay_userxattr: bool,
+    // Why each unsupported capability failed its probe.
+    #[serde(default)]
+    pub failures: BTreeMap<String, String>,
+}
+
+impl Capabilities {
+    // Inside an unprivileged user namespace trusted xattrs cannot be written, but overlayfs
+    // mounted with `userxattr` reads its opaque markers from user.overlay.* instead. A device
+    // booting in the initial namespace never switches, whatever the cached probe says.
+    pub fn rootless_overlay(&self) -> bool {
+        !self.tmpfs_xattr && self.overlay_userxattr && in_user_namespace()
+    }
+}
+
+// The initial user namespace maps the whole uid range onto itself.
+fn in_user_namespace() -> bool {
+    *USER_NAMESPACE.get_or_init(|| {
+        fs::read_to_string("/proc/self/uid_map")
+            .is_ok_and(|map| map.split_whitespace().collect::<Vec<_>>() != ["0", "0", "4294967295"])
+    })
+}
+
+fn kernel_release() -> String {
+    fs::read_to_string("/proc/sys/kernel/osrelease")
+        .map(|s| s.trim().to_string())
+        .unwrap_or_default()
+}
+
+fn xattr_roundtrip(path: &Path, name: &str, value: &[u8]) -> Result<()> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        use extattr::{Flags as XattrFlags, lgetxattr, lsetxattr};
+
+        lsetxattr(path, name, value, XattrFlags::empty())
+            .with_context(|| format!("setxattr {name}"))?;
+        let read = lgetxattr(path, name).with_context(|| format!("getxattr {name}"))?;
+        if read.strip_suffix(b"\0").unwrap_or(&read) != value {
+            bail!("{name} did not read back as written");
+        }
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let _ = (path, name, value);
+    Ok(())
+}
+
+fn probe_overlay(base: &Path, userxattr: bool) -> Result<()> {
+    let target = base.join("overlay");
+    mount(
+        "mh_probe",
+        &target,
+        "overlay",
+        MountFlags::empty(),
+        Some(
+            std::ffi::CString::new(format!(
+                "lowerdir={}:{}{}",
+                base.join("upper").display(),
+                base.join("lower").display(),
+                if userxattr { ",userxattr" } else { "" }
+            ))?
+            .as_c_str(),
+        ),
+    )
+    .context("mount overlay")?;
+    let _ = unmount(&target, UnmountFlags::DETACH);
+    Ok(())
+}
+
+// Opening a filesystem context is enough to tell whether the kernel knows the fs type, and
+// is the cheapest way to exercise fsopen itself.
+fn probe_fsopen(fs_type: &str) -> Result<()> {
+    fsopen(fs_type, FsOpenFlags::FSOPEN_CLOEXEC).with_context(|| format!("fsopen {fs_type}"))?;
+    Ok(())
+}
+
+fn probe_lowerdir_append(base: &Path) -> Result<()> {
+    let fs = fsopen("overlay", FsOpenFlags::FSOPEN_CLOEXEC)?;
+    let fs = fs.as_fd();
+    for layer in ["upper", "lower"] {
+        fsconfig_set_string(fs, "lowerdir+", base.join(layer).to_string_lossy().as_ref())
+            .context("lowerdir+ rejected")?;
+    }
+    fsconfig_set_string(fs, "source", "mh_probe")?;
+    fsconfig_create(fs)?;
+    fsmount(fs, FsMountFlags::FSMOUNT_CLOEXEC, MountAttrFlags::empty())?;
+    Ok(())
+}
+
+fn probe_erofs() -> Result<()> {
+    if probe_fsopen("erofs").is_ok() {
+        return Ok(());
+    }
+    let filesystems = fs::read_to_string("/proc/filesystems")?;
+    if !filesystems.lines().any(|l| l.trim_end().ends_with("erofs")) {
+        bail!("erofs is not registered");
+    }
+    Ok(())
+}
+
+fn probe_loop() -> Result<()> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/loop-control")
+        .context("open /dev/loop-control")?;
+    Ok(())
+}
+
+fn probe_user_xattr() -> Result<()> {
+    let file = Path::new(defs::RUN_DIR).join(".probe_user_xattr");
+    fs::write(&file, "")?;
+    let result = xattr_roundtrip(&file, "user.meta_hybrid", b"1");
+    let _ = fs::remove_file(&file);
+    result
+}
+
+fn record(caps: &mut Capabilities, name: &str, result: Result<()>) -> bool {
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            log::debug!("Capability probe {} failed: {:#}", name, e);
+            caps.failures.insert(name.to_string(), format!("{:#}", e));
+            false
+        }
+    }
+}
+
+// Everything that needs a scratch filesystem runs inside a private tmpfs, so the probes
+// behave like the storage backend will and leave nothing behind.
+fn probe_in_workspace(caps: &mut Capabilities) {
+    let base = Path::new(defs::PROBE_DIR);
+
+    let fail_all = |caps: &mut Capabilities, e: anyhow::Error| {
+        let reason = format!("{:#}", e);
+        for name in [
+            "tmpfs_xattr",
+            "overlayfs",
+            "overlay_lowerdir_append",
+            "selinux_label",
+            "overlay_userxattr",
+        ] {
+            caps.failures.insert(name.to_string(), reason.clone());
+        }
+    };
+
+    if let Err(e) = crate::sys::mount::mount_tmpfs(base, "mh_probe") {
+        fail_all(caps, e);
+        return;
+    }
+    let _ = mount_change(base, MountPropagationFlags::PRIVATE);
+
+    let prepared = ["upper", "lower", "overlay"]
+        .iter()
+        .try_for_each(|d| utils::ensure_dir_exists(base.join(d)))
+        .and_then(|_| fs::write(base.join("upper/file"), "").map_err(Into::into));
+
+    if let Err(e) = prepared {
+        log::warn!("Failed to prepare capability probe workspace: {:#}", e);
+        fail_all(caps, e);
+    } else {
+        let file = base.join("upper/file");
+        caps.tmpfs_xattr = record(
+            caps,
+            "tmpfs_xattr",
+            xattr_roundtrip(&file, defs::REPLACE_DIR_XATTR, b"y"),
+        );
+        caps.selinux_label = record(
+            caps,
+            "selinux_label",
+            xattr_roundtrip(&file, "security.selinux", PROBE_CONTEXT.as_bytes()),
+        );
+        caps.overlayfs = record(caps, "overlayfs", probe_overlay(base, false));
+        caps.overlay_userxattr = record(
+            caps,
+            "overlay_userxattr",
+            xattr_roundtrip(&file, defs::USER_REPLACE_DIR_XATTR, b"y")
+                .and_then(|_| probe_overlay(base, true)),
+        );
+        caps.overlay_lowerdir_append = caps.new_mount_api
+            && record(caps, "overlay_lowerdir_append", probe_lowerdir_append(base));
+    }
+
+    let _ = unmount(base, UnmountFlags::DETACH);
+    let _ = fs::remove_dir(base);
+}
+
+pub fn probe() -> Capabilities {
+    let mut caps = Capabilities {
+        kernel_release: kernel_release(),
+        probed_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        ..Default::default()
+    };
+
+    caps.new_mount_api = record(&mut caps, "new_mount_api", probe_fsopen("tmpfs"));
+    caps.erofs = record(&mut caps, "erofs", probe_erofs());
+    caps.loop_device = record(&mut caps, "loop_device", probe_loop());
+    caps.user_xattr = record(&mut caps, "user_xattr", probe_user_xattr());
+    probe_in_workspace(&mut caps);
+
+    caps
+}
+
+fn load_cached() -> Option<Capabilities> {
+    let content = fs::read_to_string(defs::CAPABILITIES_FILE).ok()?;
+    let caps: Capabilities = serde_json::from_str(&content).ok()?;
+
+    (caps.kernel_release == kernel_release()).then_some(caps)
+}
+
+// Failed probes are cached like passing ones, since every probe mounts at a shared scratch
+// directory; `doctor --refresh` is the way to probe this kernel again.
+fn store(caps: &Capabilities) -> Result<()> {
+    utils::atomic_write(defs::CAPABILITIES_FILE, serde_json::to_string(caps)?)
+        .context("Failed to cache capability probe")
+}
+
+// Every probe mounts at the same scratch directory, so concurrent processes take turns. The
+// lock goes away with the file handle.
+fn with_probe_lock<T>(f: impl FnOnce() -> T) -> T {
+    let lock = utils::ensure_dir_exists(defs::RUN_DIR).and_then(|_| {
+        let file = fs::File::options()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(defs::PROBE_LOCK_FILE)?;
+        flock(&file, FlockOperation::LockExclusive)?;
+        Ok(file)
+    });
+    if let Err(e) = &lock {
+        log::debug!("Probing without the probe lock: {:#}", e);
+    }
+
+    f()
+}
+
+// Probes again and replaces the cached results for this kernel.
+pub fn refresh() -> Result<Capabilities> {
+    with_probe_lock(|| {
+        let caps = probe();
+        store(&caps)?;
+        Ok(caps)
+    })
+}
+
+// The xattr that marks a directory opaque for the overlays this process mounts.
+pub fn opaque_xattr() -> &'static str {
+    if capabilities().rootless_overlay() {
+        defs::USER_REPLACE_DIR_XATTR
+    } else {
+        defs::REPLACE_DIR_XATTR
+    }
+}
+
+pub fn capabilities() -> &'static Capabilities {
+    CAPABILITIES.get_or_init(|| {
+        load_cached().unwrap_or_else(|| {
+            // Whoever held the lock before us may have just cached this kernel's results.
+            with_probe_lock(|| {
+                load_cached().unwrap_or_else(|| {
+                    let caps = probe();
+                    if let Err(e) = store(&caps) {
+                        log::warn!("{:#}", e);
+                    }
+                    caps
+                })
+            })
+        })
+    })
+}
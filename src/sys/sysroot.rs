@@ -0,0 +1,108 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    collections::VecDeque,
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+// Symlink hops allowed while resolving one path, matching the kernel's MAXSYMLINKS.
+const MAX_SYMLINKS: usize = 40;
+
+// Resolves device paths such as `/system/etc` against the system root in use. On a live
+// device that is `/` itself; otherwise it is a directory holding extracted partition trees,
+// and absolute symlinks inside it are followed relative to that directory, like a chroot.
+#[derive(Debug, Clone, Copy)]
+pub struct SysRoot<'a> {
+    root: &'a Path,
+}
+
+impl<'a> SysRoot<'a> {
+    pub fn new(root: &'a Path) -> Self {
+        Self { root }
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.root.as_os_str().is_empty() || self.root == Path::new("/")
+    }
+
+    pub fn root(&self) -> &Path {
+        if self.is_live() {
+            Path::new("/")
+        } else {
+            self.root
+        }
+    }
+
+    // Where a device path lives on this machine, without following any symlinks.
+    pub fn host_path(&self, path: &Path) -> PathBuf {
+        if self.is_live() {
+            return path.to_path_buf();
+        }
+        self.root.join(path.strip_prefix("/").unwrap_or(path))
+    }
+
+    // The device path a symlink points to, unresolved.
+    pub fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::read_link(self.host_path(path))
+    }
+
+    // Resolves every symlink in `path` and returns the result as a device path.
+    pub fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        if self.is_live() {
+            return path.canonicalize();
+        }
+
+        let mut resolved = PathBuf::from("/");
+        let mut pending: VecDeque<PathBuf> = path
+            .components()
+            .map(|c| PathBuf::from(c.as_os_str()))
+            .collect();
+        let mut hops = 0;
+
+        while let Some(next) = pending.pop_front() {
+            match next.components().next() {
+                Some(Component::Normal(name)) => {
+                    let candidate = resolved.join(name);
+                    let host = self.host_path(&candidate);
+                    if !fs::symlink_metadata(&host)?.file_type().is_symlink() {
+                        resolved = candidate;
+                        continue;
+                    }
+
+                    hops += 1;
+                    if hops > MAX_SYMLINKS {
+                        return Err(io::Error::from_raw_os_error(libc::ELOOP));
+                    }
+                    let target = fs::read_link(&host)?;
+                    if target.is_absolute() {
+                        resolved = PathBuf::from("/");
+                    }
+                    for component in target.components().rev() {
+                        pending.push_front(PathBuf::from(component.as_os_str()));
+                    }
+                }
+                Some(Component::ParentDir) => {
+                    resolved.pop();
+                }
+                _ => {}
+            }
+        }
+
+        Ok(resolved)
+    }
+
+    pub fn exists(&self, path: &Path) -> bool {
+        self.canonicalize(path).is_ok()
+    }
+
+    pub fn is_dir(&self, path: &Path) -> bool {
+        self.canonicalize(path)
+            .is_ok_and(|p| self.host_path(&p).is_dir())
+    }
+
+    pub fn is_symlink(&self, path: &Path) -> bool {
+        self.host_path(path).is_symlink()
+    }
+}
@@ -2,20 +2,26 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     ffi::CString,
+    fmt,
     fs::{self, File, OpenOptions},
-    io::Write,
-    os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt, symlink},
-    path::Path,
+    io::{self, Write},
+    os::unix::fs::{FileExt, FileTypeExt, MetadataExt, PermissionsExt, lchown, symlink},
+    path::{Path, PathBuf},
     time::{SystemTime, UNIX_EPOCH},
 };
 
 use anyhow::{Context, Result, bail};
-use rustix::fs::ioctl_ficlone;
+use rustix::{
+    fs::{AtFlags, CWD, SeekFrom, Timespec, Timestamps, ioctl_ficlone, seek, utimensat},
+    io::Errno,
+};
+use sha2::{Digest, Sha256};
 use walkdir::WalkDir;
 
 use super::xattr::{internal_apply_system_context, internal_copy_extended_attributes};
+use crate::sys::sysroot::SysRoot;
 
 pub fn atomic_write<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, content: C) -> Result<()> {
     let path = path.as_ref();
@@ -47,6 +53,14 @@ pub fn atomic_write<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, content: C) -> Resu
     Ok(())
 }
 
+pub fn hash_file<P: AsRef<Path>>(path: P) -> Result<String> {
+    let mut file = File::open(path.as_ref())
+        .with_context(|| format!("Failed to open {}", path.as_ref().display()))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
 pub fn ensure_dir_exists<T: AsRef<Path>>(dir: T) -> Result<()> {
     if !dir.as_ref().exists() {
         fs::create_dir_all(&dir)?;
@@ -64,11 +78,47 @@ pub fn reflink_or_copy(src: &Path, dest: &Path) -> Result<u64> {
         dest_file.set_permissions(metadata.permissions())?;
         return Ok(len);
     }
+
+    let metadata = src_file.metadata()?;
+    if metadata.blocks() * 512 < metadata.len() {
+        dest_file.set_permissions(metadata.permissions())?;
+        return copy_sparse(&src_file, &dest_file, metadata.len());
+    }
     drop(dest_file);
     drop(src_file);
     fs::copy(src, dest).map_err(|e| e.into())
 }
 
+// Copies only the data regions of a file and leaves the holes unallocated.
+fn copy_sparse(src: &File, dest: &File, len: u64) -> Result<u64> {
+    let mut buf = vec![0u8; 128 * 1024];
+    let mut offset = 0;
+
+    while offset < len {
+        let data = match seek(src, SeekFrom::Data(offset)) {
+            Ok(data) => data,
+            Err(Errno::NXIO) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let hole = seek(src, SeekFrom::Hole(data))?.min(len);
+
+        let mut pos = data;
+        while pos < hole {
+            let want = buf.len().min((hole - pos) as usize);
+            let read = src.read_at(&mut buf[..want], pos)?;
+            if read == 0 {
+                break;
+            }
+            dest.write_all_at(&buf[..read], pos)?;
+            pos += read as u64;
+        }
+        offset = hole;
+    }
+
+    dest.set_len(len)?;
+    Ok(len)
+}
+
 fn make_device_node(path: &Path, mode: u32, rdev: u64) -> Result<()> {
     let c_path = CString::new(path.as_os_str().as_encoded_bytes())?;
     let dev = rdev as libc::dev_t;
@@ -81,28 +131,93 @@ fn make_device_node(path: &Path, mode: u32, rdev: u64) -> Result<()> {
     Ok(())
 }
 
-fn native_cp_r(
+// A metadata step that failed on one entry whose content was copied fine.
+#[derive(Debug)]
+pub struct MetadataFailure {
+    pub path: PathBuf,
+    pub error: anyhow::Error,
+}
+
+impl fmt::Display for MetadataFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {:#}", self.path.display(), self.error)
+    }
+}
+
+#[derive(Default)]
+struct CopyState {
+    visited: HashSet<(u64, u64)>,
+    // First copy of every multiply-linked inode, so later names become hardlinks to it.
+    links: HashMap<(u64, u64), PathBuf>,
+    failures: Vec<MetadataFailure>,
+}
+
+// Ownership goes before the mode because chown clears setuid/setgid, and timestamps go last
+// because every other step may touch them. When repairing, the root of a copy is only given a
+// derived label, since its source is the module directory rather than a system path.
+fn apply_metadata(
     src: &Path,
+    metadata: &fs::Metadata,
     dst: &Path,
     relative: &Path,
-    repair: bool,
-    visited: &mut HashSet<(u64, u64)>,
-) -> Result<()> {
-    if !dst.exists() {
-        if src.is_dir() {
-            fs::create_dir_all(dst)?;
-        }
-        if let Ok(src_meta) = src.metadata() {
-            let _ = fs::set_permissions(dst, src_meta.permissions());
+    repair: Option<SysRoot>,
+    failures: &mut Vec<MetadataFailure>,
+) {
+    let mut record = |result: Result<()>| {
+        if let Err(error) = result {
+            failures.push(MetadataFailure {
+                path: dst.to_path_buf(),
+                error,
+            });
         }
+    };
+    let is_symlink = metadata.file_type().is_symlink();
 
-        if repair && relative.as_os_str().is_empty() {
-            let _ = internal_apply_system_context(dst, relative);
-        } else if !repair {
-            let _ = internal_copy_extended_attributes(src, dst);
+    match repair {
+        Some(sysroot) if relative.as_os_str().is_empty() => {
+            record(internal_apply_system_context(dst, relative, sysroot));
         }
+        _ => {
+            record(internal_copy_extended_attributes(src, dst));
+            if let Some(sysroot) = repair {
+                record(internal_apply_system_context(dst, relative, sysroot));
+            }
+        }
+    }
+
+    record(
+        lchown(dst, Some(metadata.uid()), Some(metadata.gid()))
+            .with_context(|| format!("chown {}:{}", metadata.uid(), metadata.gid())),
+    );
+
+    if !is_symlink {
+        record(
+            fs::set_permissions(dst, metadata.permissions())
+                .with_context(|| format!("chmod {:o}", metadata.mode() & 0o7777)),
+        );
     }
 
+    let times = Timestamps {
+        last_access: Timespec {
+            tv_sec: metadata.atime(),
+            tv_nsec: metadata.atime_nsec() as _,
+        },
+        last_modification: Timespec {
+            tv_sec: metadata.mtime(),
+            tv_nsec: metadata.mtime_nsec() as _,
+        },
+    };
+    record(utimensat(CWD, dst, &times, AtFlags::SYMLINK_NOFOLLOW).context("set timestamps"));
+}
+
+fn native_cp_r(
+    src: &Path,
+    dst: &Path,
+    relative: &Path,
+    repair: Option<SysRoot>,
+    filter: &dyn Fn(&Path, bool) -> bool,
+    state: &mut CopyState,
+) -> Result<()> {
     for entry in fs::read_dir(src)? {
         let entry = entry?;
         let src_path = entry.path();
@@ -112,14 +227,18 @@ fn native_cp_r(
 
         let metadata = entry.metadata()?;
         let ft = metadata.file_type();
-        let dev = metadata.dev();
-        let ino = metadata.ino();
+        let key = (metadata.dev(), metadata.ino());
+
+        if !filter(&next_relative, ft.is_dir()) {
+            continue;
+        }
 
         if ft.is_dir() {
-            if !visited.insert((dev, ino)) {
+            if !state.visited.insert(key) {
                 continue;
             }
-            native_cp_r(&src_path, &dst_path, &next_relative, repair, visited)?;
+            ensure_dir_exists(&dst_path)?;
+            native_cp_r(&src_path, &dst_path, &next_relative, repair, filter, state)?;
         } else if ft.is_symlink() {
             if dst_path.exists() {
                 fs::remove_file(&dst_path)?;
@@ -133,32 +252,161 @@ fn native_cp_r(
             let mode = metadata.permissions().mode();
             let rdev = metadata.rdev();
             make_device_node(&dst_path, mode, rdev)?;
+        } else if let Some(first) = state.links.get(&key) {
+            // The inode already carries its metadata from the first name.
+            fs::hard_link(first, &dst_path)?;
+            continue;
         } else {
             reflink_or_copy(&src_path, &dst_path)?;
+            if metadata.nlink() > 1 {
+                state.links.insert(key, dst_path.clone());
+            }
         }
 
-        let _ = internal_copy_extended_attributes(&src_path, &dst_path);
-
-        if repair {
-            let _ = internal_apply_system_context(&dst_path, &next_relative);
-        }
+        apply_metadata(
+            &src_path,
+            &metadata,
+            &dst_path,
+            &next_relative,
+            repair,
+            &mut state.failures,
+        );
     }
     Ok(())
 }
 
-pub fn sync_dir(src: &Path, dst: &Path, repair_context: bool) -> Result<()> {
+// Copies `src` into `dst`, skipping every entry `filter` rejects. With `repair_context` set,
+// labels missing from the source are derived from that system root. The filter sees the path
+// relative to `src` and whether it is a directory; a rejected directory is not descended into.
+// Ownership, timestamps, hardlinks and sparse regions are preserved. Metadata that could not
+// be applied does not fail the copy and is returned per entry instead.
+pub fn sync_dir<F>(
+    src: &Path,
+    dst: &Path,
+    repair_context: Option<SysRoot>,
+    filter: F,
+) -> Result<Vec<MetadataFailure>>
+where
+    F: Fn(&Path, bool) -> bool,
+{
     if !src.exists() {
-        return Ok(());
+        return Ok(Vec::new());
     }
     ensure_dir_exists(dst)?;
-    let mut visited = HashSet::new();
-    native_cp_r(src, dst, Path::new(""), repair_context, &mut visited).with_context(|| {
-        format!(
-            "Failed to natively sync {} to {}",
-            src.display(),
-            dst.display()
-        )
-    })
+    let mut state = CopyState::default();
+    native_cp_r(src, dst, Path::new(""), repair_context, &filter, &mut state).with_context(
+        || {
+            format!(
+                "Failed to natively sync {} to {}",
+                src.display(),
+                dst.display()
+            )
+        },
+    )?;
+
+    let metadata = src.metadata()?;
+    apply_metadata(
+        src,
+        &metadata,
+        dst,
+        Path::new(""),
+        repair_context,
+        &mut state.failures,
+    );
+
+    Ok(state.failures)
+}
+
+// Brings one entry of a synced tree in line with its source without touching its siblings.
+// Files, symlinks and device nodes are staged next to the target and renamed over it, so
+// readers see either the old or the new entry; directories are created or updated in place.
+pub fn sync_entry(
+    src: &Path,
+    dst: &Path,
+    relative: &Path,
+    repair_context: Option<SysRoot>,
+) -> Result<Vec<MetadataFailure>> {
+    let metadata = src
+        .symlink_metadata()
+        .with_context(|| format!("Failed to stat {}", src.display()))?;
+    let ft = metadata.file_type();
+    let mut failures = Vec::new();
+
+    if let Ok(existing) = dst.symlink_metadata()
+        && existing.is_dir() != ft.is_dir()
+    {
+        if existing.is_dir() {
+            fs::remove_dir_all(dst)?;
+        } else {
+            fs::remove_file(dst)?;
+        }
+    }
+
+    if ft.is_dir() {
+        ensure_dir_exists(dst)?;
+        apply_metadata(src, &metadata, dst, relative, repair_context, &mut failures);
+        return Ok(failures);
+    }
+
+    if let Some(parent) = dst.parent() {
+        ensure_dir_exists(parent)?;
+    }
+
+    let file_name = dst
+        .file_name()
+        .with_context(|| format!("Invalid sync target {}", dst.display()))?;
+    let staging = dst.with_file_name(format!(".{}.staging", file_name.to_string_lossy()));
+    if staging.symlink_metadata().is_ok() {
+        fs::remove_file(&staging)?;
+    }
+
+    let staged = (|| -> Result<()> {
+        if ft.is_symlink() {
+            symlink(fs::read_link(src)?, &staging)?;
+        } else if ft.is_char_device() || ft.is_block_device() || ft.is_fifo() {
+            make_device_node(&staging, metadata.permissions().mode(), metadata.rdev())?;
+        } else {
+            reflink_or_copy(src, &staging)?;
+        }
+        apply_metadata(
+            src,
+            &metadata,
+            &staging,
+            relative,
+            repair_context,
+            &mut failures,
+        );
+        fs::rename(&staging, dst)?;
+        Ok(())
+    })();
+
+    if staged.is_err() {
+        let _ = fs::remove_file(&staging);
+    }
+    staged.with_context(|| format!("Failed to sync {} to {}", src.display(), dst.display()))?;
+
+    // Failures were recorded against the staging name.
+    for failure in &mut failures {
+        failure.path = dst.to_path_buf();
+    }
+    Ok(failures)
+}
+
+// Updates ownership, mode, timestamps, xattrs and SELinux context of an entry whose content is
+// already current.
+pub fn sync_entry_metadata(
+    src: &Path,
+    dst: &Path,
+    relative: &Path,
+    repair_context: Option<SysRoot>,
+) -> Result<Vec<MetadataFailure>> {
+    let metadata = src
+        .symlink_metadata()
+        .with_context(|| format!("Failed to stat {}", src.display()))?;
+    let mut failures = Vec::new();
+
+    apply_metadata(src, &metadata, dst, relative, repair_context, &mut failures);
+    Ok(failures)
 }
 
 pub fn prune_empty_dirs<P: AsRef<Path>>(root: P) -> Result<()> {
@@ -1,51 +1,64 @@
 // Copyright 2026 Hybrid Mount Developers
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-#[cfg(any(target_os = "linux", target_os = "android"))]
-use std::os::unix::ffi::OsStrExt;
 use std::path::Path;
 #[cfg(any(target_os = "linux", target_os = "android"))]
-use std::process::Command;
+use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
 
 use anyhow::{Context, Result};
 #[cfg(any(target_os = "linux", target_os = "android"))]
 use extattr::{Flags as XattrFlags, lgetxattr, llistxattr, lsetxattr};
 
+use crate::sys::{
+    file_contexts::{self, FileKind},
+    probe,
+    sysroot::SysRoot,
+};
+
 const SELINUX_XATTR: &str = "security.selinux";
-const OVERLAY_OPAQUE_XATTR: &str = "trusted.overlay.opaque";
 const CONTEXT_SYSTEM: &str = "u:object_r:system_file:s0";
 const CONTEXT_VENDOR: &str = "u:object_r:vendor_file:s0";
 const CONTEXT_HAL: &str = "u:object_r:same_process_hal_file:s0";
 const CONTEXT_VENDOR_EXEC: &str = "u:object_r:vendor_file:s0";
 const CONTEXT_ROOTFS: &str = "u:object_r:rootfs:s0";
 
+// Every attribute is attempted; the first one that could not be set is returned.
 fn copy_extended_attributes(src: &Path, dst: &Path) -> Result<()> {
     #[cfg(any(target_os = "linux", target_os = "android"))]
     {
-        if let Ok(mut ctx) = lgetfilecon(src) {
-            if ctx.contains("u:object_r:rootfs:s0") {
-                ctx = CONTEXT_SYSTEM.to_string();
+        let mut first_error = None;
+        let mut set = |name: &OsStr, value: &[u8]| {
+            if let Err(e) = lsetxattr(dst, name, value, XattrFlags::empty())
+                && first_error.is_none()
+            {
+                first_error =
+                    Some(anyhow::Error::from(e).context(format!("set {}", name.to_string_lossy())));
             }
-            let _ = lsetfilecon(dst, &ctx);
-        } else {
-            let _ = lsetfilecon(dst, CONTEXT_SYSTEM);
-        }
-        if let Ok(opaque) = lgetxattr(src, OVERLAY_OPAQUE_XATTR) {
-            let _ = lsetxattr(dst, OVERLAY_OPAQUE_XATTR, &opaque, XattrFlags::empty());
-        }
+        };
+
+        let ctx = match lgetfilecon(src) {
+            Ok(ctx) if !ctx.contains(CONTEXT_ROOTFS) => ctx,
+            _ => CONTEXT_SYSTEM.to_string(),
+        };
+        set(OsStr::new(SELINUX_XATTR), ctx.as_bytes());
+
         if let Ok(xattrs) = llistxattr(src) {
             for xattr_name in xattrs {
-                let name_bytes = xattr_name.as_bytes();
-                let name_str = String::from_utf8_lossy(name_bytes);
+                let name_str = String::from_utf8_lossy(xattr_name.as_bytes());
 
                 #[allow(clippy::collapsible_if)]
-                if name_str.starts_with("trusted.overlay.") && name_str != OVERLAY_OPAQUE_XATTR {
+                if name_str.starts_with("trusted.overlay.") || name_str.starts_with("user.overlay.")
+                {
                     if let Ok(val) = lgetxattr(src, &xattr_name) {
-                        let _ = lsetxattr(dst, &xattr_name, &val, XattrFlags::empty());
+                        set(&xattr_name, &val);
                     }
                 }
             }
         }
+
+        if let Some(e) = first_error {
+            return Err(e);
+        }
     }
     Ok(())
 }
@@ -55,7 +68,7 @@ pub fn set_overlay_opaque<P: AsRef<Path>>(path: P) -> Result<()> {
     {
         lsetxattr(
             path.as_ref(),
-            OVERLAY_OPAQUE_XATTR,
+            probe::opaque_xattr(),
             b"y",
             XattrFlags::empty(),
         )?;
@@ -66,14 +79,19 @@ pub fn set_overlay_opaque<P: AsRef<Path>>(path: P) -> Result<()> {
 pub fn lsetfilecon<P: AsRef<Path>>(path: P, con: &str) -> Result<()> {
     #[cfg(any(target_os = "linux", target_os = "android"))]
     {
-        if let Err(e) = lsetxattr(
+        lsetxattr(
             path.as_ref(),
             SELINUX_XATTR,
             con.as_bytes(),
             XattrFlags::empty(),
-        ) {
-            let _ = e;
-        }
+        )
+        .with_context(|| {
+            format!(
+                "Failed to set SELinux context {} on {}",
+                con,
+                path.as_ref().display()
+            )
+        })?;
     }
     Ok(())
 }
@@ -96,36 +114,6 @@ pub fn lgetfilecon<P: AsRef<Path>>(_path: P) -> Result<String> {
     unimplemented!();
 }
 
-pub fn is_overlay_xattr_supported() -> Result<bool> {
-    #[cfg(any(target_os = "linux", target_os = "android"))]
-    {
-        let output = Command::new("zcat")
-            .arg("/proc/config.gz")
-            .output()
-            .context("Failed to read config.gz")
-            .unwrap();
-        let config = String::from_utf8_lossy(&output.stdout);
-
-        for i in config.lines() {
-            if i.starts_with("#") {
-                continue;
-            }
-
-            let Some((k, v)) = i.split_once('=') else {
-                continue;
-            };
-
-            if k.trim() == "CONFIG_TMPFS_XATTR" && v.trim() == "y" {
-                return Ok(true);
-            }
-        }
-
-        Ok(false)
-    }
-    #[cfg(not(any(target_os = "linux", target_os = "android")))]
-    Ok(true)
-}
-
 fn guess_context_by_path(path: &Path) -> &'static str {
     let path_str = path.to_string_lossy();
 
@@ -148,7 +136,7 @@ fn guess_context_by_path(path: &Path) -> &'static str {
     CONTEXT_SYSTEM
 }
 
-fn apply_system_context(current: &Path, relative: &Path) -> Result<()> {
+fn apply_system_context(current: &Path, relative: &Path, sysroot: SysRoot) -> Result<()> {
     if let Some(name) = current.file_name().and_then(|n| n.to_str())
         && (name == "upperdir" || name == "workdir")
         && let Some(parent) = current.parent()
@@ -167,8 +155,15 @@ fn apply_system_context(current: &Path, relative: &Path) -> Result<()> {
     }
 
     let system_path = Path::new("/").join(relative);
-    if system_path.exists() {
-        if let Ok(sys_ctx) = lgetfilecon(&system_path) {
+    if let Some(contexts) = file_contexts::for_root(sysroot.root())
+        && let Ok(metadata) = current.symlink_metadata()
+        && let Some(ctx) = contexts.lookup(&system_path, FileKind::of(&metadata.file_type()))
+    {
+        return lsetfilecon(current, ctx);
+    }
+
+    if sysroot.exists(&system_path) {
+        if let Ok(sys_ctx) = lgetfilecon(sysroot.host_path(&system_path)) {
             let target_ctx = if sys_ctx == CONTEXT_ROOTFS {
                 CONTEXT_SYSTEM
             } else {
@@ -177,8 +172,8 @@ fn apply_system_context(current: &Path, relative: &Path) -> Result<()> {
             return lsetfilecon(current, target_ctx);
         }
     } else if let Some(parent) = system_path.parent()
-        && parent.exists()
-        && let Ok(parent_ctx) = lgetfilecon(parent)
+        && sysroot.exists(parent)
+        && let Ok(parent_ctx) = lgetfilecon(sysroot.host_path(parent))
         && parent_ctx != CONTEXT_ROOTFS
     {
         let guessed = guess_context_by_path(&system_path);
@@ -196,6 +191,10 @@ pub(crate) fn internal_copy_extended_attributes(src: &Path, dst: &Path) -> Resul
     copy_extended_attributes(src, dst)
 }
 
-pub(crate) fn internal_apply_system_context(current: &Path, relative: &Path) -> Result<()> {
-    apply_system_context(current, relative)
+pub(crate) fn internal_apply_system_context(
+    current: &Path,
+    relative: &Path,
+    sysroot: SysRoot,
+) -> Result<()> {
+    apply_system_context(current, relative, sysroot)
 }
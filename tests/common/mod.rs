@@ -0,0 +1,305 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+#![allow(dead_code)]
+
+use std::{
+    env,
+    ffi::CString,
+    fs,
+    io::{self, Write},
+    os::unix::fs::{FileTypeExt, symlink},
+    path::{Path, PathBuf},
+    process::{Child, Command, Output},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use rustix::mount::{MountFlags, mount, mount_bind_recursive};
+use serde_json::Value;
+
+// Set in the re-executed test binary once it runs inside the namespaces.
+const INSIDE_ENV: &str = "META_HYBRID_TEST_SANDBOX";
+
+// Host directories the daemon needs to run from inside the fake root.
+const HOST_DIRS: &[&str] = &[
+    "usr", "bin", "sbin", "lib", "lib32", "lib64", "etc", "dev", "proc",
+];
+
+const DEFAULT_CONFIG: &str = r#"
+moduledir = "/data/adb/modules"
+mountsource = "KSU"
+verbose = true
+disable_umount = true
+hybrid_mnt_dir = "/debug_ramdisk"
+"#;
+
+// Where a paused daemon reports in and waits to be resumed.
+const PAUSE_FIFO: &str = "/pause.fifo";
+
+// A throwaway device: a tmpfs root holding the partitions, /data/adb and the daemon binary,
+// which only exists inside the user and mount namespaces of one test.
+pub struct Sandbox {
+    pub root: PathBuf,
+}
+
+fn namespaces_available() -> bool {
+    Command::new("unshare")
+        .args(["--user", "--map-root-user", "--mount", "true"])
+        .output()
+        .is_ok_and(|out| out.status.success())
+}
+
+// Runs `test` again inside fresh user and mount namespaces and returns the sandbox there.
+// The outer call only reports the inner result and returns None, as it does when the host
+// does not allow unprivileged namespaces.
+pub fn enter(test: &str) -> Option<Sandbox> {
+    if env::var_os(INSIDE_ENV).is_some() {
+        return Some(Sandbox::new(test));
+    }
+
+    if !namespaces_available() {
+        // Straight to stderr, since the harness swallows eprintln! output of passing tests.
+        let _ = writeln!(
+            io::stderr(),
+            "skipping {test}: unprivileged user namespaces are unavailable"
+        );
+        return None;
+    }
+
+    let exe = env::current_exe().expect("test binary path");
+    let output = Command::new("unshare")
+        .args(["--user", "--map-root-user", "--mount", "--"])
+        .arg(exe)
+        .args([test, "--exact", "--nocapture", "--test-threads=1"])
+        .env(INSIDE_ENV, "1")
+        .output()
+        .expect("failed to run unshare");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    eprintln!("{stderr}");
+    assert!(
+        output.status.success(),
+        "{test} failed inside the sandbox\n--- stdout\n{stdout}\n--- stderr\n{stderr}"
+    );
+    assert!(
+        stdout.contains("1 passed"),
+        "{test} did not run inside the sandbox\n{stdout}"
+    );
+
+    None
+}
+
+impl Sandbox {
+    fn new(test: &str) -> Self {
+        let root = Path::new(env!("CARGO_TARGET_TMPDIR"))
+            .join("sandbox")
+            .join(test);
+        fs::create_dir_all(&root).expect("create sandbox root");
+        mount("sandbox", &root, "tmpfs", MountFlags::empty(), None).expect("mount sandbox tmpfs");
+
+        for name in HOST_DIRS {
+            let host = Path::new("/").join(name);
+            let inner = root.join(name);
+            if host.is_symlink() {
+                symlink(fs::read_link(&host).unwrap(), &inner).unwrap();
+            } else if host.is_dir() {
+                fs::create_dir(&inner).unwrap();
+                mount_bind_recursive(&host, &inner)
+                    .unwrap_or_else(|e| panic!("bind {}: {e}", host.display()));
+            }
+        }
+
+        let daemon = root.join("meta-hybrid");
+        fs::write(&daemon, "").unwrap();
+        mount_bind_recursive(env!("CARGO_BIN_EXE_meta-hybrid"), &daemon).expect("bind daemon");
+
+        let sandbox = Self { root };
+        sandbox.mkdir("/data/adb/modules");
+        sandbox.write("/data/adb/meta-hybrid/config.toml", DEFAULT_CONFIG);
+        sandbox
+    }
+
+    // Where a device path lives from the test's point of view.
+    pub fn path(&self, device_path: &str) -> PathBuf {
+        self.root.join(device_path.trim_start_matches('/'))
+    }
+
+    pub fn mkdir(&self, device_path: &str) {
+        fs::create_dir_all(self.path(device_path)).unwrap();
+    }
+
+    pub fn write(&self, device_path: &str, content: &str) {
+        let path = self.path(device_path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    pub fn symlink(&self, device_path: &str, target: &str) {
+        let path = self.path(device_path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        symlink(target, path).unwrap();
+    }
+
+    // An overlayfs whiteout: a 0:0 character device.
+    pub fn whiteout(&self, device_path: &str) {
+        let path = self.path(device_path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        rustix::fs::mknodat(
+            rustix::fs::CWD,
+            &path,
+            rustix::fs::FileType::CharacterDevice,
+            rustix::fs::Mode::from_raw_mode(0o644),
+            0,
+        )
+        .unwrap_or_else(|e| panic!("mknod {}: {e}", path.display()));
+    }
+
+    // Creates /data/adb/modules/<id> with a module.prop and returns its device path.
+    pub fn module(&self, id: &str) -> String {
+        let dir = format!("/data/adb/modules/{id}");
+        self.write(
+            &format!("{dir}/module.prop"),
+            &format!("id={id}\nname={id}\nversion=1\nversionCode=1\n"),
+        );
+        dir
+    }
+
+    // Two stacked overlays on /vendor (also reachable as /system/vendor), so the kernel refuses
+    // a third level (FILESYSTEM_MAX_STACK_DEPTH) and any overlay below /vendor fails.
+    pub fn unmountable_vendor(&self) {
+        self.write("/stack/base/lib/libstock.so", "stock");
+        self.mkdir("/stack/empty");
+        self.mkdir("/stack/inner");
+        self.mkdir("/vendor");
+        self.symlink("/system/vendor", "/vendor");
+
+        let overlay = |lower: &str, target: &str| {
+            let options = CString::new(format!(
+                "lowerdir={}:{}",
+                self.path("/stack/empty").display(),
+                self.path(lower).display()
+            ))
+            .unwrap();
+            mount(
+                "stack",
+                self.path(target),
+                "overlay",
+                MountFlags::empty(),
+                Some(options.as_c_str()),
+            )
+            .unwrap();
+        };
+        overlay("/stack/base", "/stack/inner");
+        overlay("/stack/inner", "/vendor");
+    }
+
+    pub fn config(&self, extra: &str) {
+        self.write(
+            "/data/adb/meta-hybrid/config.toml",
+            &format!("{DEFAULT_CONFIG}{extra}"),
+        );
+    }
+
+    // Runs the daemon's boot sequence with the sandbox as its root directory.
+    pub fn boot(&self) -> Output {
+        self.run(&[])
+    }
+
+    pub fn run(&self, args: &[&str]) -> Output {
+        let output = self
+            .daemon(args)
+            .output()
+            .expect("failed to run the daemon");
+
+        eprintln!(
+            "meta-hybrid {args:?}: {}\n--- stdout\n{}\n--- stderr\n{}",
+            output.status,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        output
+    }
+
+    // Starts the daemon in the background, for long-running commands such as `serve`.
+    pub fn spawn(&self, args: &[&str]) -> Child {
+        self.daemon(args)
+            .spawn()
+            .expect("failed to start the daemon")
+    }
+
+    // Starts the daemon in the background so it stops right before the mount step named
+    // `point` (or "rollback"); see wait_paused() and resume().
+    pub fn spawn_paused(&self, args: &[&str], point: &str) -> Child {
+        rustix::fs::mknodat(
+            rustix::fs::CWD,
+            self.path(PAUSE_FIFO),
+            rustix::fs::FileType::Fifo,
+            rustix::fs::Mode::from_raw_mode(0o600),
+            0,
+        )
+        .expect("create pause fifo");
+
+        self.daemon(args)
+            .env("META_HYBRID_PAUSE_AT", point)
+            .env("META_HYBRID_PAUSE_FIFO", PAUSE_FIFO)
+            .spawn()
+            .expect("failed to start the daemon")
+    }
+
+    // Blocks until the daemon reports that it reached its pause point.
+    pub fn wait_paused(&self) -> String {
+        let fifo = self.path(PAUSE_FIFO);
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || tx.send(fs::read_to_string(fifo)));
+        rx.recv_timeout(Duration::from_secs(30))
+            .expect("the daemon never reached its pause point")
+            .expect("read pause fifo")
+    }
+
+    pub fn resume(&self) {
+        fs::write(self.path(PAUSE_FIFO), "resume").expect("resume the daemon");
+    }
+
+    fn daemon(&self, args: &[&str]) -> Command {
+        let mut command = Command::new("chroot");
+        command
+            .arg(&self.root)
+            .arg("/meta-hybrid")
+            .args(args)
+            .env("RUST_LOG", "debug");
+        command
+    }
+
+    pub fn read(&self, device_path: &str) -> Option<String> {
+        fs::read_to_string(self.path(device_path)).ok()
+    }
+
+    pub fn exists(&self, device_path: &str) -> bool {
+        self.path(device_path).symlink_metadata().is_ok()
+    }
+
+    pub fn is_whiteout(&self, device_path: &str) -> bool {
+        self.path(device_path)
+            .symlink_metadata()
+            .is_ok_and(|m| m.file_type().is_char_device())
+    }
+
+    pub fn list(&self, device_path: &str) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(self.path(device_path))
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    pub fn state(&self) -> Value {
+        let content = self
+            .read("/data/adb/meta-hybrid/run/daemon_state.json")
+            .expect("daemon wrote no runtime state");
+        serde_json::from_str(&content).unwrap()
+    }
+}
@@ -0,0 +1,282 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+mod common;
+
+use std::fs;
+
+use rustix::mount::{MountFlags, mount};
+
+#[test]
+fn overlay_merges_module_files() {
+    let Some(sb) = common::enter("overlay_merges_module_files") else {
+        return;
+    };
+
+    sb.write("/system/bin/stock", "stock");
+    sb.write("/system/bin/changed", "stock");
+    let module = sb.module("overlay_add");
+    sb.write(&format!("{module}/system/bin/added"), "module");
+    sb.write(&format!("{module}/system/bin/changed"), "module");
+
+    assert!(sb.boot().status.success());
+
+    assert_eq!(sb.list("/system/bin"), ["added", "changed", "stock"]);
+    assert_eq!(sb.read("/system/bin/changed").as_deref(), Some("module"));
+    assert_eq!(sb.read("/system/bin/stock").as_deref(), Some("stock"));
+
+    let state = sb.state();
+    assert_eq!(state["storage_mode"], "tmpfs");
+    assert_eq!(state["overlay_modules"], serde_json::json!(["overlay_add"]));
+    assert_eq!(state["magic_modules"], serde_json::json!([]));
+}
+
+#[test]
+fn overlay_replace_dir_hides_stock_entries() {
+    let Some(sb) = common::enter("overlay_replace_dir_hides_stock_entries") else {
+        return;
+    };
+
+    sb.write("/system/app/Browser/Browser.apk", "stock");
+    sb.write("/system/app/Browser/oat/arm64/Browser.odex", "stock");
+    sb.write("/system/app/Camera/Camera.apk", "stock");
+    let module = sb.module("browser_swap");
+    sb.write(&format!("{module}/system/app/Browser/.replace"), "");
+    sb.write(
+        &format!("{module}/system/app/Browser/Browser.apk"),
+        "module",
+    );
+
+    assert!(sb.boot().status.success());
+
+    assert!(!sb.exists("/system/app/Browser/oat"));
+    assert_eq!(
+        sb.read("/system/app/Browser/Browser.apk").as_deref(),
+        Some("module")
+    );
+    assert_eq!(
+        sb.read("/system/app/Camera/Camera.apk").as_deref(),
+        Some("stock")
+    );
+}
+
+#[test]
+fn overlay_whiteout_removes_stock_file() {
+    let Some(sb) = common::enter("overlay_whiteout_removes_stock_file") else {
+        return;
+    };
+
+    sb.write("/system/etc/hosts", "stock");
+    sb.write("/system/etc/fonts.xml", "stock");
+    let module = sb.module("hosts_remover");
+    sb.whiteout(&format!("{module}/system/etc/hosts"));
+
+    assert!(sb.boot().status.success());
+
+    assert_eq!(sb.list("/system/etc"), ["fonts.xml"]);
+    assert_eq!(sb.state()["module_stats"]["hosts_remover"]["whiteouts"], 1);
+}
+
+#[test]
+fn magic_rule_binds_module_files() {
+    let Some(sb) = common::enter("magic_rule_binds_module_files") else {
+        return;
+    };
+
+    sb.write("/system/lib/libstock.so", "stock");
+    sb.write("/system/lib/libold.so", "stock");
+    sb.write("/system/etc/permissions/stock.xml", "stock");
+    let module = sb.module("magic_mod");
+    sb.write(&format!("{module}/system/lib/libstock.so"), "module");
+    sb.write(&format!("{module}/system/lib/libnew.so"), "module");
+    sb.whiteout(&format!("{module}/system/lib/libold.so"));
+    sb.write(&format!("{module}/system/etc/permissions/.replace"), "");
+    sb.write(
+        &format!("{module}/system/etc/permissions/module.xml"),
+        "module",
+    );
+    sb.config("\n[rules.magic_mod]\ndefault_mode = \"magic\"\n");
+
+    assert!(sb.boot().status.success());
+
+    assert_eq!(sb.list("/system/lib"), ["libnew.so", "libstock.so"]);
+    assert_eq!(
+        sb.read("/system/lib/libstock.so").as_deref(),
+        Some("module")
+    );
+    assert!(!sb.exists("/system/etc/permissions/stock.xml"));
+    assert!(sb.exists("/system/etc/permissions/module.xml"));
+
+    let state = sb.state();
+    assert_eq!(state["magic_modules"], serde_json::json!(["magic_mod"]));
+    assert_eq!(state["overlay_modules"], serde_json::json!([]));
+}
+
+#[test]
+fn nested_rule_split_keeps_module_precedence() {
+    let Some(sb) = common::enter("nested_rule_split_keeps_module_precedence") else {
+        return;
+    };
+
+    sb.write("/system/etc/init/boot.rc", "stock");
+    sb.write("/system/etc/fonts/font.ttf", "stock");
+    sb.write("/system/etc/hosts", "stock");
+    let low = sb.module("low_split");
+    sb.write(&format!("{low}/system/etc/init/boot.rc"), "low");
+    sb.write(&format!("{low}/system/etc/fonts/font.ttf"), "low");
+    let high = sb.module("high_whole");
+    sb.write(&format!("{high}/system/etc/init/boot.rc"), "high");
+    sb.write(&format!("{high}/system/etc/hosts"), "high");
+    sb.config(
+        r#"
+[rules.low_split]
+priority = 1
+[rules.low_split.paths]
+"system/etc/fonts" = "magic"
+
+[rules.high_whole]
+priority = 10
+"#,
+    );
+
+    assert!(sb.boot().status.success());
+
+    // The low module's rule splits /system/etc, yet the high module still wins inside it.
+    assert_eq!(sb.read("/system/etc/init/boot.rc").as_deref(), Some("high"));
+    assert_eq!(sb.read("/system/etc/hosts").as_deref(), Some("high"));
+    assert_eq!(
+        sb.read("/system/etc/fonts/font.ttf").as_deref(),
+        Some("low")
+    );
+}
+
+#[test]
+fn failed_overlay_falls_back_to_magic_mount() {
+    let Some(sb) = common::enter("failed_overlay_falls_back_to_magic_mount") else {
+        return;
+    };
+
+    sb.unmountable_vendor();
+    sb.write("/system/bin/stock", "stock");
+    let module = sb.module("vendor_blob");
+    sb.write(&format!("{module}/vendor/lib/libblob.so"), "module");
+    sb.write(&format!("{module}/system/bin/tool"), "module");
+    // Magic Mount only takes partitions other than /system from the configured list.
+    sb.config("partitions = [\"vendor\"]\n");
+
+    assert!(sb.boot().status.success());
+
+    assert_eq!(sb.list("/vendor/lib"), ["libblob.so", "libstock.so"]);
+    assert_eq!(sb.read("/vendor/lib/libblob.so").as_deref(), Some("module"));
+    assert_eq!(sb.read("/system/bin/tool").as_deref(), Some("module"));
+
+    let state = sb.state();
+    assert_eq!(state["magic_modules"], serde_json::json!(["vendor_blob"]));
+    let degraded = &state["degraded_mounts"][0];
+    assert_eq!(degraded["target"], "/vendor/lib");
+    assert_eq!(degraded["strategy"], "magic_fallback");
+    assert_eq!(degraded["modules"], serde_json::json!(["vendor_blob"]));
+}
+
+#[test]
+fn disabled_and_skip_mount_modules_stay_unmounted() {
+    let Some(sb) = common::enter("disabled_and_skip_mount_modules_stay_unmounted") else {
+        return;
+    };
+
+    sb.write("/system/bin/stock", "stock");
+    for id in ["disabled_mod", "skipped_mod"] {
+        let module = sb.module(id);
+        sb.write(&format!("{module}/system/bin/{id}"), "module");
+    }
+    sb.write("/data/adb/modules/disabled_mod/disable", "");
+    sb.write("/data/adb/modules/skipped_mod/skip_mount", "");
+
+    assert!(sb.boot().status.success());
+
+    assert_eq!(sb.list("/system/bin"), ["stock"]);
+    assert!(
+        fs::read_dir(sb.path("/debug_ramdisk"))
+            .unwrap()
+            .flatten()
+            .all(|e| e.file_name() != "disabled_mod" && e.file_name() != "skipped_mod")
+    );
+}
+
+#[test]
+fn rollback_spares_foreign_mounts() {
+    let Some(sb) = common::enter("rollback_spares_foreign_mounts") else {
+        return;
+    };
+
+    sb.unmountable_vendor();
+    let module = sb.module("vendor_blob");
+    sb.write(&format!("{module}/vendor/lib/libblob.so"), "module");
+    let other = sb.module("other");
+    sb.write("/system/dir0/stock", "stock");
+    sb.write(&format!("{other}/system/dir0/added"), "module");
+    sb.mkdir("/foreign");
+    sb.config("rollback_policy = \"degraded\"\n");
+
+    // Someone else mounts between two of the daemon's steps.
+    let mut daemon = sb.spawn_paused(&[], "overlay /vendor/lib");
+    sb.wait_paused();
+    mount(
+        "foreign",
+        sb.path("/foreign"),
+        "tmpfs",
+        MountFlags::empty(),
+        None,
+    )
+    .unwrap();
+    sb.write("/foreign/marker", "foreign");
+    sb.resume();
+
+    assert!(!daemon.wait().unwrap().success());
+
+    assert_eq!(sb.read("/foreign/marker").as_deref(), Some("foreign"));
+    assert_eq!(sb.list("/system/dir0"), ["stock"]);
+    assert_eq!(sb.list("/vendor/lib"), ["libstock.so"]);
+    let journal = sb
+        .read("/data/adb/meta-hybrid/run/mount_journal.jsonl")
+        .unwrap_or_default();
+    assert!(!journal.contains("/foreign"));
+}
+
+#[test]
+fn rollback_leaves_covered_mounts() {
+    let Some(sb) = common::enter("rollback_leaves_covered_mounts") else {
+        return;
+    };
+
+    sb.unmountable_vendor();
+    let module = sb.module("vendor_blob");
+    sb.write(&format!("{module}/vendor/lib/libblob.so"), "module");
+    let other = sb.module("other");
+    sb.write("/system/dir0/stock", "stock");
+    sb.write(&format!("{other}/system/dir0/added"), "module");
+    sb.config("rollback_policy = \"degraded\"\n");
+
+    // Someone else mounts on top of one of the daemon's overlays before it rolls back.
+    let mut daemon = sb.spawn_paused(&[], "rollback");
+    sb.wait_paused();
+    assert_eq!(sb.list("/system/dir0"), ["added", "stock"]);
+    mount(
+        "foreign",
+        sb.path("/system/dir0"),
+        "tmpfs",
+        MountFlags::empty(),
+        None,
+    )
+    .unwrap();
+    sb.write("/system/dir0/marker", "foreign");
+    sb.resume();
+
+    assert!(!daemon.wait().unwrap().success());
+
+    assert_eq!(sb.read("/system/dir0/marker").as_deref(), Some("foreign"));
+    let journal = sb
+        .read("/data/adb/meta-hybrid/run/mount_journal.jsonl")
+        .unwrap_or_default();
+    assert!(journal.contains(r#""step":"overlay /system/dir0""#));
+}
@@ -0,0 +1,208 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+mod common;
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    os::unix::{fs::PermissionsExt, net::UnixStream},
+    process::Child,
+    thread,
+    time::{Duration, Instant},
+};
+
+use rustix::mount::{UnmountFlags, unmount};
+use serde_json::{Value, json};
+
+const SOCKET: &str = "/data/adb/meta-hybrid/run/control.sock";
+
+struct Client {
+    daemon: Child,
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    next_id: u64,
+    events: Vec<Value>,
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+impl Client {
+    fn start(sb: &common::Sandbox, args: &[&str]) -> Self {
+        let mut daemon = sb.spawn(&[&["serve"], args].concat());
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        let stream = loop {
+            if let Ok(stream) = UnixStream::connect(sb.path(SOCKET)) {
+                break stream;
+            }
+            assert!(
+                daemon.try_wait().unwrap().is_none(),
+                "serve exited before listening"
+            );
+            assert!(Instant::now() < deadline, "serve never opened its socket");
+            thread::sleep(Duration::from_millis(50));
+        };
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+
+        Self {
+            daemon,
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+            next_id: 1,
+            events: Vec::new(),
+        }
+    }
+
+    fn send_line(&mut self, line: &str) {
+        self.writer
+            .write_all(format!("{line}\n").as_bytes())
+            .unwrap();
+    }
+
+    fn receive(&mut self) -> Value {
+        let mut line = String::new();
+        self.reader
+            .read_line(&mut line)
+            .expect("no message from serve");
+        serde_json::from_str(&line).unwrap()
+    }
+
+    // Returns the whole response, keeping any event notifications that arrive before it.
+    fn call(&mut self, method: &str, params: Value) -> Value {
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        self.send_line(&request.to_string());
+
+        loop {
+            let message = self.receive();
+            if message["method"] == "event" {
+                self.events.push(message["params"].clone());
+            } else {
+                assert_eq!(message["id"], id);
+                return message;
+            }
+        }
+    }
+
+    fn event(&mut self, kind: &str) -> Value {
+        loop {
+            if let Some(pos) = self.events.iter().position(|e| e["type"] == kind) {
+                return self.events.remove(pos);
+            }
+            let message = self.receive();
+            assert_eq!(message["method"], "event", "unexpected message {message}");
+            self.events.push(message["params"].clone());
+        }
+    }
+}
+
+#[test]
+fn serve_answers_json_rpc_requests() {
+    let Some(sb) = common::enter("serve_answers_json_rpc_requests") else {
+        return;
+    };
+
+    sb.write("/system/bin/stock", "stock");
+    let module = sb.module("rpc_mod");
+    sb.write(&format!("{module}/system/bin/tool"), "module");
+    assert!(sb.boot().status.success());
+
+    let mut client = Client::start(&sb, &["--drift-interval", "0"]);
+
+    let mode = sb.path(SOCKET).metadata().unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    let modules = client.call("modules.list", Value::Null);
+    assert_eq!(modules["result"][0]["id"], "rpc_mod");
+
+    let config = client.call("config.get", Value::Null);
+    assert_eq!(config["result"]["moduledir"], "/data/adb/modules");
+
+    let status = client.call("status", json!({ "verify": true }));
+    assert_eq!(status["result"]["healthy"], true);
+
+    let unknown = client.call("modules.frobnicate", Value::Null);
+    assert_eq!(unknown["error"]["code"], -32601);
+
+    let missing = client.call("modules.set", json!({ "id": "rpc_mod" }));
+    assert_eq!(missing["error"]["code"], -32602);
+
+    let failed = client.call("snapshots.restore", json!({ "id": "no-such-snapshot" }));
+    assert_eq!(failed["error"]["code"], -32000);
+    assert_eq!(failed["error"]["data"]["kind"], "snapshot_missing");
+
+    let typo = client.call("modules.reload", json!({ "id": "rpc_mdo" }));
+    assert_eq!(typo["error"]["data"]["kind"], "module_not_found");
+
+    client.send_line("{not json");
+    assert_eq!(client.receive()["error"]["code"], -32700);
+
+    // Notifications are not answered, so the next response belongs to the next call.
+    client.send_line(r#"{"jsonrpc":"2.0","method":"modules.list"}"#);
+    let diagnostics = client.call("diagnostics", Value::Null);
+    assert!(diagnostics["result"].is_array());
+}
+
+#[test]
+fn serve_pushes_events_to_subscribers() {
+    let Some(sb) = common::enter("serve_pushes_events_to_subscribers") else {
+        return;
+    };
+
+    sb.write("/system/bin/stock", "stock");
+    let module = sb.module("rpc_mod");
+    sb.write(&format!("{module}/system/bin/tool"), "module");
+    assert!(sb.boot().status.success());
+
+    let mut client = Client::start(&sb, &["--drift-interval", "1"]);
+    let subscribed = client.call("events.subscribe", Value::Null);
+    assert!(subscribed["result"]["subscription"].is_u64());
+
+    sb.write(&format!("{module}/system/bin/tool"), "reloaded");
+    let reload = client.call("modules.reload", json!({ "id": "rpc_mod" }));
+    assert!(reload["error"].is_null(), "reload failed: {reload}");
+    assert_eq!(sb.read("/system/bin/tool").as_deref(), Some("reloaded"));
+
+    let progress = client.event("sync_progress");
+    assert_eq!(progress["module"], "rpc_mod");
+    assert_eq!(progress["outcome"], "synced");
+
+    unmount(sb.path("/system/bin"), UnmountFlags::DETACH).unwrap();
+
+    let drift = client.event("drift");
+    assert_eq!(drift["healthy"], false);
+    assert_eq!(drift["missing"], 1);
+}
+
+#[test]
+fn serve_relays_boot_events() {
+    let Some(sb) = common::enter("serve_relays_boot_events") else {
+        return;
+    };
+
+    sb.write("/system/bin/stock", "stock");
+    let module = sb.module("rpc_mod");
+    sb.write(&format!("{module}/system/bin/tool"), "module");
+
+    let mut client = Client::start(&sb, &["--drift-interval", "0"]);
+    client.call("events.subscribe", Value::Null);
+
+    assert!(sb.boot().status.success());
+
+    let progress = client.event("sync_progress");
+    assert_eq!(progress["module"], "rpc_mod");
+    assert_eq!(progress["outcome"], "synced");
+    assert_eq!(progress["total"], 1);
+
+    let result = client.event("mount_result");
+    assert_eq!(result["overlay_modules"], json!(["rpc_mod"]));
+    assert!(result["failed_step"].is_null());
+}
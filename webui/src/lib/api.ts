@@ -16,6 +16,8 @@ import type {
   DiagnosticIssue,
   Silo,
   ModuleRules,
+  CliError,
+  CliErrorCode,
 } from "./types";
 
 interface KsuExecResult {
@@ -39,6 +41,26 @@ try {
 
 const shouldUseMock = import.meta.env.DEV || !ksuExec;
 
+export class CommandError extends Error {
+  code: CliErrorCode;
+
+  constructor(code: CliErrorCode, message: string) {
+    super(message);
+    this.code = code;
+  }
+}
+
+// Failed commands print `{code, message}` JSON on stderr.
+function commandError(stderr: string): CommandError {
+  try {
+    const parsed = JSON.parse(stderr) as CliError;
+    if (parsed.code) return new CommandError(parsed.code, parsed.message);
+  } catch {
+    // not structured output
+  }
+  return new CommandError("internal", stderr);
+}
+
 function formatBytes(bytes: number, decimals = 2): string {
   if (!+bytes) return "0 B";
   const k = 1024;
@@ -319,7 +341,7 @@ const RealAPI: AppAPI = {
     if (!ksuExec) return [];
     try {
       const { errno, stdout } = await ksuExec(
-        `${PATHS.BINARY} system-action --action granary-list`,
+        `${PATHS.BINARY} snapshot list`,
       );
       if (errno === 0 && stdout) return JSON.parse(stdout);
     } catch {
@@ -329,21 +351,21 @@ const RealAPI: AppAPI = {
   },
   createSilo: async (reason: string): Promise<void> => {
     if (!ksuExec) return;
-    const cmd = `${PATHS.BINARY} system-action --action granary-create --value "${reason}"`;
+    const cmd = `${PATHS.BINARY} snapshot create --reason "${reason}"`;
     const { errno, stderr } = await ksuExec(cmd);
-    if (errno !== 0) throw new Error(stderr);
+    if (errno !== 0) throw commandError(stderr);
   },
   deleteSilo: async (siloId: string): Promise<void> => {
     if (!ksuExec) return;
-    const cmd = `${PATHS.BINARY} system-action --action granary-delete --value "${siloId}"`;
+    const cmd = `${PATHS.BINARY} snapshot delete "${siloId}"`;
     const { errno, stderr } = await ksuExec(cmd);
-    if (errno !== 0) throw new Error(stderr);
+    if (errno !== 0) throw commandError(stderr);
   },
   restoreSilo: async (siloId: string): Promise<void> => {
     if (!ksuExec) return;
-    const cmd = `${PATHS.BINARY} system-action --action granary-restore --value "${siloId}"`;
+    const cmd = `${PATHS.BINARY} snapshot restore "${siloId}"`;
     const { errno, stderr } = await ksuExec(cmd);
-    if (errno !== 0) throw new Error(stderr);
+    if (errno !== 0) throw commandError(stderr);
   },
 };
 
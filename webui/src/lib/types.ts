@@ -11,19 +11,30 @@ export interface BackupConfig {
 export interface ModuleRules {
   default_mode: MountMode;
   paths: Record<string, string>;
+  priority?: number;
 }
 
 export type OverlayMode = "tmpfs" | "ext4" | "erofs";
 
+export type RollbackPolicy = "never" | "critical" | "degraded";
+
+export type SyncMode = "full" | "delta";
+
 export interface AppConfig {
   moduledir: string;
   mountsource: string;
   verbose: boolean;
   hybrid_mnt_dir: string;
+  sysroot?: string;
   partitions: string[];
   overlay_mode: OverlayMode;
   disable_umount: boolean;
   allow_umount_coexistence: boolean;
+  rollback_policy?: RollbackPolicy;
+  sync_hash?: boolean;
+  sync_mode?: SyncMode;
+  sync_include?: string[];
+  sync_exclude?: string[];
   logfile?: string;
   backup: BackupConfig;
 }
@@ -34,8 +45,13 @@ export interface Module {
   id: string;
   name: string;
   version: string;
+  version_code?: number | null;
   author: string;
   description: string;
+  update_json?: string | null;
+  extra_props?: Record<string, string>;
+  prop_warnings?: string[];
+  storage?: ModuleStats | null;
   mode: string;
   is_mounted: boolean;
   enabled?: boolean;
@@ -43,6 +59,18 @@ export interface Module {
   rules: ModuleRules;
 }
 
+export interface FootprintStats {
+  bytes: number;
+  files: number;
+  dirs: number;
+  symlinks: number;
+  whiteouts: number;
+}
+
+export interface ModuleStats extends FootprintStats {
+  partitions: Record<string, FootprintStats>;
+}
+
 export interface StorageStatus {
   size: string;
   used: string;
@@ -86,10 +114,25 @@ export interface ModeStats {
   magic: number;
 }
 
+export type EntryKind = "file" | "directory" | "symlink" | "whiteout";
+
+export type DeliveryMode = "overlay" | "magic";
+
+export interface ShadowedModule {
+  module: string;
+  mode: DeliveryMode;
+  kind: EntryKind;
+  state: "hidden" | "merged";
+}
+
 export interface ConflictEntry {
   partition: string;
   relative_path: string;
   contending_modules: string[];
+  winner: string;
+  winner_mode: DeliveryMode;
+  winner_kind: EntryKind;
+  shadowed: ShadowedModule[];
 }
 
 export interface Silo {
@@ -102,6 +145,19 @@ export interface Silo {
   raw_state?: string;
 }
 
+export type CliErrorCode =
+  | "internal"
+  | "config_invalid"
+  | "module_not_found"
+  | "snapshot_missing"
+  | "mount_failure"
+  | "permission_denied";
+
+export interface CliError {
+  code: CliErrorCode;
+  message: string;
+}
+
 export interface DiagnosticIssue {
   level: "Info" | "Warning" | "Critical";
   context: string;
@@ -4,7 +4,7 @@
  */
 
 import { createSignal, createMemo, onMount, Show, For } from "solid-js";
-import { API } from "../lib/api";
+import { API, CommandError } from "../lib/api";
 import { store } from "../lib/store";
 import type { Silo } from "../lib/types";
 import Skeleton from "../components/Skeleton";
@@ -47,6 +47,12 @@ export default function GranaryTab() {
 
   const L_G = createMemo(() => store.L.granary || {});
   const L_C = createMemo(() => store.L.common || {});
+  const L_E = createMemo(() => store.L.errors || {});
+
+  function errorText(e: unknown) {
+    if (e instanceof CommandError) return L_E()[e.code] || e.message;
+    return (e as Error).message;
+  }
 
   async function loadSilos() {
     setLoading(true);
@@ -82,7 +88,7 @@ export default function GranaryTab() {
         "success",
       );
     } catch (e: unknown) {
-      store.showToast((e as Error).message, "error");
+      store.showToast(errorText(e), "error");
     } finally {
       setBusyId(null);
       setSelectedSilo(null);
@@ -99,7 +105,7 @@ export default function GranaryTab() {
       setSilos(silos().filter((s) => s.id !== silo.id));
       store.showToast(L_G().deleteSuccess || "Deleted", "success");
     } catch (e: unknown) {
-      store.showToast((e as Error).message, "error");
+      store.showToast(errorText(e), "error");
     } finally {
       setBusyId(null);
       setSelectedSilo(null);
@@ -121,7 +127,7 @@ export default function GranaryTab() {
       store.showToast(L_G().createSuccess || "Snapshot created", "success");
       await loadSilos();
     } catch (e: unknown) {
-      store.showToast((e as Error).message, "error");
+      store.showToast(errorText(e), "error");
     } finally {
       setIsCreating(false);
     }